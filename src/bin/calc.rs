@@ -0,0 +1,277 @@
+//! A line-oriented calculator over [`Expression`].
+//!
+//! Every line is parsed and evaluated, and its result is numbered so later
+//! lines can refer to it as `$1`, `$2`, ... Lines starting with `:` are
+//! commands, see `:help`. Blank lines and lines starting with `#` are skipped.
+//! Each expression is type-checked before it is evaluated on the stack machine
+//! of [`vm`], and input nested too deeply to check is rejected as a parse
+//! error.
+//!
+//! When stdin is not a terminal, only the values are printed and the first
+//! error stops the program with a non-zero exit status, so a file of
+//! expressions can be piped through it:
+//!
+//! ```text
+//! $ printf '2 * 21\n$1 - 2\n' | cargo run --bin calc
+//! 42
+//! 40
+//! ```
+
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process::ExitCode;
+
+use exercise::pattern_matching::{
+    Environment, Expression, Value, parser::parse, typecheck::check, vm,
+};
+
+const HELP: &str = "\
+Enter an expression such as `(3 - 4) * 5 + 10 * 9` to evaluate it.
+Results are stored as $1, $2, ... and can be used in later expressions.
+Comparisons and `true`/`false`, `let x = 2 in x * x`,
+`if $1 < 0 then -$1 else $1` and `fn sq(x) = x * x in sq($1)` work too;
+numbers and booleans cannot be mixed.
+
+:ast <expression>   show the parsed tree as an S-expression
+:history            list the results so far
+:help               show this message
+:quit               leave the calculator";
+
+/// What handling a single line produced.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    /// An expression evaluated to `value`, now bound to `$index`.
+    Value {
+        index: usize,
+        value: Value,
+    },
+    /// A command printed some text.
+    Text(String),
+    /// Nothing to print, e.g. for a blank line.
+    Nothing,
+    Quit,
+}
+
+/// The calculator state carried from one line to the next.
+#[derive(Default)]
+struct Session {
+    env: Environment,
+    /// Each evaluated line and its result; entry `i` is bound to `$(i + 1)`.
+    history: Vec<(Expression, Value)>,
+}
+
+impl Session {
+    fn handle(&mut self, line: &str) -> Result<Outcome, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(Outcome::Nothing);
+        }
+        let Some(command) = line.strip_prefix(':') else {
+            return self.evaluate(line);
+        };
+
+        let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+        match name {
+            "ast" => {
+                let e = parse(argument).map_err(|err| err.to_string())?;
+                Ok(Outcome::Text(e.sexpr().to_string()))
+            }
+            "history" => {
+                let lines: Vec<String> = self
+                    .history
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (e, value))| format!("${} = {e} = {value}", i + 1))
+                    .collect();
+                Ok(Outcome::Text(lines.join("\n")))
+            }
+            "help" => Ok(Outcome::Text(String::from(HELP))),
+            "quit" | "q" => Ok(Outcome::Quit),
+            _ => Err(format!("unknown command `:{name}`, try `:help`")),
+        }
+    }
+
+    fn evaluate(&mut self, line: &str) -> Result<Outcome, String> {
+        let e = parse(line).map_err(|err| err.to_string())?;
+        check(&e, &self.env).map_err(|err| err.to_string())?;
+        let value = vm::compile(&e).run(&self.env);
+        let value = value.map_err(|err| err.to_string())?;
+        self.history.push((e, value));
+        let index = self.history.len();
+        self.env.set(format!("${index}"), value);
+        Ok(Outcome::Value { index, value })
+    }
+}
+
+fn main() -> ExitCode {
+    let interactive = io::stdin().is_terminal();
+    let prompt = || {
+        if interactive {
+            print!("> ");
+            let _ = io::stdout().flush();
+        }
+    };
+
+    let mut session = Session::default();
+    prompt();
+    for (number, line) in io::stdin().lock().lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("error: {err}");
+                return ExitCode::FAILURE;
+            }
+        };
+        match session.handle(&line) {
+            Ok(Outcome::Value { index, value }) if interactive => println!("${index} = {value}"),
+            Ok(Outcome::Value { value, .. }) => println!("{value}"),
+            Ok(Outcome::Text(text)) => println!("{text}"),
+            Ok(Outcome::Nothing) => {}
+            Ok(Outcome::Quit) => break,
+            Err(err) if interactive => eprintln!("error: {err}"),
+            Err(err) => {
+                eprintln!("line {}: {err}", number + 1);
+                return ExitCode::FAILURE;
+            }
+        }
+        prompt();
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbered_results() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.handle("(3 - 4) * 5 + 10 * 9"),
+            Ok(Outcome::Value {
+                index: 1,
+                value: Value::Number(85)
+            })
+        );
+        assert_eq!(
+            session.handle("$1 / 5"),
+            Ok(Outcome::Value {
+                index: 2,
+                value: Value::Number(17)
+            })
+        );
+        assert_eq!(
+            session.handle("$1 - $2"),
+            Ok(Outcome::Value {
+                index: 3,
+                value: Value::Number(68)
+            })
+        );
+        assert_eq!(
+            session.handle(":history"),
+            Ok(Outcome::Text(String::from(
+                "$1 = (3 - 4) * 5 + 10 * 9 = 85\n$2 = $1 / 5 = 17\n$3 = $1 - $2 = 68"
+            )))
+        );
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.handle(":ast 1 + x * 2"),
+            Ok(Outcome::Text(String::from("(+ 1 (* x 2))")))
+        );
+        assert_eq!(session.handle("   "), Ok(Outcome::Nothing));
+        assert_eq!(session.handle("# a comment"), Ok(Outcome::Nothing));
+        assert_eq!(session.handle(":quit"), Ok(Outcome::Quit));
+        assert!(session.handle(":frobnicate").is_err());
+    }
+
+    #[test]
+    fn test_let_if_fn() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.handle("fn fact(n) = if n <= 1 then 1 else n * fact(n - 1) in fact(5)"),
+            Ok(Outcome::Value {
+                index: 1,
+                value: Value::Number(120)
+            })
+        );
+        assert_eq!(
+            session.handle("let half = $1 / 2 in half - 1"),
+            Ok(Outcome::Value {
+                index: 2,
+                value: Value::Number(59)
+            })
+        );
+        assert_eq!(
+            session.handle("fn f(n) = f(n) in f(1)"),
+            Err(String::from("recursion limit exceeded in `f(n)`"))
+        );
+    }
+
+    #[test]
+    fn test_booleans() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.handle("2 < 3 == true"),
+            Ok(Outcome::Value {
+                index: 1,
+                value: Value::Bool(true)
+            })
+        );
+        assert_eq!(
+            session.handle("if $1 then 10 else 20"),
+            Ok(Outcome::Value {
+                index: 2,
+                value: Value::Number(10)
+            })
+        );
+        // Caught before evaluation, even in the branch that is not taken.
+        assert_eq!(
+            session.handle("if $1 then $2 else $1 + 1"),
+            Err(String::from("expected a number, but `$1` is a boolean"))
+        );
+        assert_eq!(
+            session.handle(":history"),
+            Ok(Outcome::Text(String::from(
+                "$1 = 2 < 3 == true = true\n$2 = if $1 then 10 else 20 = 10"
+            )))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.handle("1 / (2 - 2)"),
+            Err(String::from("division by zero in `1 / (2 - 2)`"))
+        );
+        assert_eq!(
+            session.handle("$1 + 1"),
+            Err(String::from("unbound variable `$1`"))
+        );
+        assert!(session.handle("1 +").is_err());
+        assert!(session.history.is_empty());
+    }
+
+    #[test]
+    fn test_deep_input() {
+        let mut session = Session::default();
+        let sum = format!("{}1", "1 + ".repeat(199_999));
+        assert_eq!(
+            session.handle(&sum),
+            Ok(Outcome::Value {
+                index: 1,
+                value: Value::Number(200_000)
+            })
+        );
+        let nested = format!("{}1{}", "(".repeat(50_000), ")".repeat(50_000));
+        assert!(
+            session
+                .handle(&nested)
+                .unwrap_err()
+                .contains("less deeply nested")
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! Runs the elevator simulator on random traffic and draws it, or compares
+//! dispatch strategies on it.
+//!
+//! ```text
+//! $ cargo run --bin elevator -- render --dispatch look --passengers 10
+//! $ cargo run --bin elevator -- render --building tower.txt --out frames.txt
+//! $ cargo run --bin elevator -- compare --traffic up-peak --passengers 200
+//! ```
+//!
+//! Frames are drawn over each other on the terminal, or all written to the
+//! file given with `--out`. `compare` runs each strategy on the same traffic
+//! and prints a table of their [`metrics`]. The building is read from the
+//! file given with `--building`, in the format of [`Building::parse`]; by
+//! default it has ten floors and two cars. Every car starts at the lowest
+//! floor it stops at.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use exercise::elevator::Floor;
+use exercise::elevator::building::Building;
+use exercise::elevator::controller::ElevatorController;
+use exercise::elevator::dispatch::{Dispatch, Look, NearestCar, RoundRobin};
+use exercise::elevator::metrics;
+use exercise::elevator::render::{Output, Renderer, show};
+use exercise::elevator::simulator::{Arrival, Profile, Simulator, Timing, Traffic};
+
+const USAGE: &str = "\
+usage: elevator render [options]
+       elevator compare [options]
+
+--building <file>     the building to simulate, ten floors and two cars if not given
+--dispatch <name>     `nearest`, `look` or `round-robin`, `look` if not given; for
+                      `compare`, a comma-separated list, all of them if not given
+--traffic <profile>   `up-peak`, `down-peak` or `inter-floor`, `inter-floor` if not given
+--passengers <n>      how many passengers turn up, 20 if not given
+--seed <n>            the seed of the random traffic, 1 if not given
+--delay <ms>          the time between frames on the terminal, 500 if not given
+--out <file>          write the frames to a file instead";
+
+/// The dispatch strategies `compare` runs if not told otherwise.
+const STRATEGIES: [&str; 3] = ["nearest", "look", "round-robin"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Render,
+    Compare,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    command: Command,
+    building: Option<PathBuf>,
+    dispatch: Option<String>,
+    traffic: Profile,
+    passengers: usize,
+    seed: u64,
+    output: Output,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let Some((command, mut args)) = args.split_first() else {
+            return Err(String::from("no command given"));
+        };
+        let command = match command.as_str() {
+            "render" => Command::Render,
+            "compare" => Command::Compare,
+            _ => return Err(format!("unknown command `{command}`")),
+        };
+        let mut building = None;
+        let mut dispatch = None;
+        let mut traffic = Profile::InterFloor;
+        let (mut passengers, mut seed, mut delay) = (20, 1, 500);
+        let mut out = None;
+        while let [option, value, rest @ ..] = args {
+            let number = |value: &String| -> Result<u64, String> {
+                (value.parse()).map_err(|_| format!("`{option}` needs a number, not `{value}`"))
+            };
+            match option.as_str() {
+                "--building" => building = Some(PathBuf::from(value)),
+                "--dispatch" => dispatch = Some(value.clone()),
+                "--traffic" => traffic = profile(value)?,
+                "--passengers" => passengers = number(value)? as usize,
+                "--seed" => seed = number(value)?,
+                "--delay" => delay = number(value)?,
+                "--out" => out = Some(PathBuf::from(value)),
+                _ => return Err(format!("unknown option `{option}`")),
+            }
+            args = rest;
+        }
+        if let [option] = args {
+            return Err(format!("`{option}` needs a value"));
+        }
+        let output = match out {
+            Some(path) => Output::File(path),
+            None => Output::Stdout {
+                delay: Duration::from_millis(delay),
+            },
+        };
+        Ok(Options {
+            command,
+            building,
+            dispatch,
+            traffic,
+            passengers,
+            seed,
+            output,
+        })
+    }
+}
+
+fn dispatch(name: &str) -> Result<Box<dyn Dispatch>, String> {
+    match name {
+        "nearest" => Ok(Box::new(NearestCar)),
+        "look" => Ok(Box::new(Look)),
+        "round-robin" => Ok(Box::new(RoundRobin::default())),
+        _ => Err(format!("unknown dispatch strategy `{name}`")),
+    }
+}
+
+fn profile(name: &str) -> Result<Profile, String> {
+    match name {
+        "up-peak" => Ok(Profile::UpPeak),
+        "down-peak" => Ok(Profile::DownPeak),
+        "inter-floor" => Ok(Profile::InterFloor),
+        _ => Err(format!("unknown traffic profile `{name}`")),
+    }
+}
+
+/// The building, the floors its cars start at and the passengers turning
+/// up, as `options` has them.
+fn setup(options: &Options) -> Result<(Building, Vec<Floor>, Vec<Arrival>), String> {
+    let building = match &options.building {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .map_err(|err| format!("{}: {err}", path.display()))?;
+            Building::parse(&text).map_err(|err| format!("{}: {err}", path.display()))?
+        }
+        None => Building::new(0, 9, 2),
+    };
+    let floors: Vec<Floor> = (0..building.cars.len())
+        .map(|car| building.stops_of(car).next().unwrap_or(building.lowest))
+        .collect();
+    let traffic = Traffic {
+        passengers: options.passengers,
+        mean_interval: 5,
+        profile: options.traffic,
+    };
+    let arrivals = (traffic.arrivals(&building, options.seed))
+        .ok_or("traffic needs two floors served by the same cars")?;
+    Ok((building, floors, arrivals))
+}
+
+fn render(options: &Options) -> Result<(), String> {
+    let (building, floors, arrivals) = setup(options)?;
+    let name = options.dispatch.as_deref().unwrap_or("look");
+    let controller = ElevatorController::new(building.clone(), &floors, dispatch(name)?);
+    let report = Simulator::new(Timing::default(), controller, &arrivals).run();
+    let frames = Renderer::new(&building, &floors, &report);
+    show(frames, &options.output).map_err(|err| err.to_string())
+}
+
+/// The table of metrics of each strategy `options` asks for.
+fn compare(options: &Options) -> Result<String, String> {
+    let (building, floors, arrivals) = setup(options)?;
+    let names = match &options.dispatch {
+        Some(names) => names.split(',').collect(),
+        None => STRATEGIES.to_vec(),
+    };
+    let rows = (names.into_iter())
+        .map(|name| {
+            let controller = ElevatorController::new(building.clone(), &floors, dispatch(name)?);
+            let report = Simulator::new(Timing::default(), controller, &arrivals).run();
+            Ok((name, metrics::collect(&report, &floors)))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(metrics::table(&rows))
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = Options::parse(&args).and_then(|options| match options.command {
+        Command::Render => render(&options),
+        Command::Compare => compare(&options).map(|table| print!("{table}")),
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn test_options() {
+        let options = parse("render --dispatch nearest --seed 7 --out frames.txt").unwrap();
+        assert_eq!(
+            options,
+            Options {
+                command: Command::Render,
+                building: None,
+                dispatch: Some(String::from("nearest")),
+                traffic: Profile::InterFloor,
+                passengers: 20,
+                seed: 7,
+                output: Output::File(PathBuf::from("frames.txt")),
+            }
+        );
+        assert_eq!(
+            parse("render --delay 50").unwrap().output,
+            Output::Stdout {
+                delay: Duration::from_millis(50)
+            }
+        );
+        assert_eq!(parse(""), Err(String::from("no command given")));
+        assert_eq!(
+            parse("render --seed x"),
+            Err(String::from("`--seed` needs a number, not `x`"))
+        );
+        assert_eq!(
+            parse("render --out"),
+            Err(String::from("`--out` needs a value"))
+        );
+        assert_eq!(
+            parse("compare --traffic down-peak").unwrap().traffic,
+            Profile::DownPeak
+        );
+        assert_eq!(
+            parse("compare --traffic lunch"),
+            Err(String::from("unknown traffic profile `lunch`"))
+        );
+        assert!(dispatch("fastest").is_err());
+    }
+
+    #[test]
+    fn test_setup() {
+        let path = std::env::temp_dir().join("elevator-one-floor.txt");
+        std::fs::write(&path, "floors 0 0\ncars 1\n").unwrap();
+        let options = parse(&format!("render --building {}", path.display())).unwrap();
+        assert_eq!(
+            setup(&options).map(|_| ()),
+            Err(String::from(
+                "traffic needs two floors served by the same cars"
+            ))
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_compare() {
+        let table = compare(&parse("compare --traffic up-peak --passengers 30").unwrap());
+        let table = table.unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 1 + STRATEGIES.len());
+        assert!(lines[0].starts_with("dispatch"));
+        for (line, name) in lines[1..].iter().zip(STRATEGIES) {
+            assert!(line.starts_with(name) && line.contains(" 30/30 "), "{line}");
+        }
+
+        let options = parse("compare --dispatch look,fastest").unwrap();
+        assert_eq!(
+            compare(&options),
+            Err(String::from("unknown dispatch strategy `fastest`"))
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use core::prelude;
 
+use crate::generic_functions::Logger;
+
 fn apply_and_log(
     func: impl FnOnce(&'static str) -> String,
     func_name: &'static str,
@@ -8,20 +10,8 @@ fn apply_and_log(
     println!("calling {func_name}({input}): {}", func(input))
 }
 
-pub trait Logger {
-    /// Log a message at the given verbosity level.
-    fn log(&self, verbosity: u8, message: &str);
-}
-
-struct StderrLogger;
-
-impl Logger for StderrLogger {
-    fn log(&self, verbosity: u8, message: &str) {
-        eprintln!("verbosity={verbosity}: {message}");
-    }
-}
-
-struct Filter<L, P> {
+/// Only passes on messages for which `predicate` returns true.
+pub struct Filter<L, P> {
     inner: L,
     predicate: P,
 }
@@ -31,7 +21,7 @@ where
     L: Logger,
     P: Fn(u8, &str) -> bool,
 {
-    fn new(inner: L, predicate: P) -> Self {
+    pub fn new(inner: L, predicate: P) -> Self {
         Self { inner, predicate }
     }
 }
@@ -50,7 +40,8 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::closures::{Filter, Logger, StderrLogger, apply_and_log};
+    use crate::closures::{Filter, Logger, apply_and_log};
+    use crate::generic_functions::StderrLogger;
 
     #[test]
     fn test_closure_syntax() {
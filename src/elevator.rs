@@ -0,0 +1,277 @@
+//! The events of an elevator system with one or more cars in a [`building`],
+//! a [`controller`] that reacts to them, [`dispatch`] strategies sharing lobby
+//! calls out between the cars, and a [`simulator`] to run it all in, whose
+//! runs can be saved as a [`log`], checked against safety [`invariants`],
+//! measured with [`metrics`] and drawn with [`render`].
+
+use std::fmt;
+use std::str::{FromStr, SplitWhitespace};
+
+pub mod building;
+pub mod controller;
+pub mod dispatch;
+pub mod invariants;
+pub mod log;
+pub mod metrics;
+pub mod render;
+pub mod simulator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An event in the elevator system that the controller must react to.
+pub enum Event {
+    /// A button was pressed.
+    ButtonPressed(Button),
+
+    /// The car has arrived at the given floor.
+    CarArrived(CarId, Floor),
+
+    /// The car's doors have opened.
+    CarDoorOpened(CarId),
+
+    /// The car's doors have closed.
+    CarDoorClosed(CarId),
+
+    /// A passenger of the given weight got on the car.
+    PassengerBoarded(CarId, Weight),
+
+    /// A passenger of the given weight got off the car.
+    PassengerAlighted(CarId, Weight),
+
+    /// The car was switched to another mode of operation.
+    ModeChanged(CarId, Mode),
+
+    /// The car's power came back after an outage, leaving it with its door
+    /// closed somewhere in the shaft, and the controller with no record of
+    /// its calls.
+    PowerRestored(CarId),
+}
+
+impl Event {
+    /// The car the event is about, if any.
+    pub fn car(&self) -> Option<CarId> {
+        match *self {
+            Event::ButtonPressed(Button::LobbyCall(..)) => None,
+            Event::ButtonPressed(Button::CarFloor(car, _))
+            | Event::CarArrived(car, _)
+            | Event::CarDoorOpened(car)
+            | Event::CarDoorClosed(car)
+            | Event::PassengerBoarded(car, _)
+            | Event::PassengerAlighted(car, _)
+            | Event::ModeChanged(car, _)
+            | Event::PowerRestored(car) => Some(car),
+        }
+    }
+}
+
+/// How a car is operated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// In service, answering calls.
+    #[default]
+    Normal,
+
+    /// Out of service: no lobby calls are given to the car, which only
+    /// goes where it is sent from inside.
+    Maintenance,
+
+    /// Recalled by the fire alarm: the car drops its calls, goes straight to
+    /// the given floor and stays there with its door open, ignoring buttons.
+    FireRecall(Floor),
+}
+
+/// A floor is represented as an integer.
+pub type Floor = i32;
+
+/// Cars are numbered from zero.
+pub type CarId = usize;
+
+/// A weight in kilograms, as the car's load sensor reports it.
+pub type Weight = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A direction of travel.
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Up => write!(f, "up"),
+            Direction::Down => write!(f, "down"),
+        }
+    }
+}
+
+impl Direction {
+    pub fn reverse(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+        }
+    }
+
+    /// The floor next to `floor` in this direction.
+    pub fn step(self, floor: Floor) -> Floor {
+        match self {
+            Direction::Up => floor + 1,
+            Direction::Down => floor - 1,
+        }
+    }
+
+    /// Whether `to` lies beyond `from` in this direction.
+    pub fn is_beyond(self, from: Floor, to: Floor) -> bool {
+        match self {
+            Direction::Up => to > from,
+            Direction::Down => to < from,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    /// A button in the elevator lobby on the given floor.
+    LobbyCall(Direction, Floor),
+
+    /// A floor button within the given car.
+    CarFloor(CarId, Floor),
+}
+
+/// The car has arrived on the given floor.
+pub fn car_arrived(car: CarId, floor: i32) -> Event {
+    Event::CarArrived(car, floor)
+}
+
+/// The car doors have opened.
+pub fn car_door_opened(car: CarId) -> Event {
+    Event::CarDoorOpened(car)
+}
+
+/// The car doors have closed.
+pub fn car_door_closed(car: CarId) -> Event {
+    Event::CarDoorClosed(car)
+}
+
+/// A passenger got on the car.
+pub fn passenger_boarded(car: CarId, weight: Weight) -> Event {
+    Event::PassengerBoarded(car, weight)
+}
+
+/// A passenger got off the car.
+pub fn passenger_alighted(car: CarId, weight: Weight) -> Event {
+    Event::PassengerAlighted(car, weight)
+}
+
+/// The car was switched to another mode of operation.
+pub fn mode_changed(car: CarId, mode: Mode) -> Event {
+    Event::ModeChanged(car, mode)
+}
+
+/// The car's power came back after an outage.
+pub fn power_restored(car: CarId) -> Event {
+    Event::PowerRestored(car)
+}
+
+/// A directional button was pressed in an elevator lobby on the given floor.
+pub fn lobby_call_button_pressed(floor: i32, dir: Direction) -> Event {
+    Event::ButtonPressed(Button::LobbyCall(dir, floor))
+}
+
+/// A floor buton was pressed in the given elevator car.
+pub fn car_floor_button_pressed(car: CarId, floor: i32) -> Event {
+    Event::ButtonPressed(Button::CarFloor(car, floor))
+}
+
+/// A line of a text file, such as a [`log`] or a [`building`] description,
+/// that could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// The number of the line, counting from one.
+    pub line: usize,
+    /// What the parser was looking for.
+    pub expected: &'static str,
+    /// What it found instead.
+    pub found: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: expected {}, found {}",
+            self.line, self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The whitespace-separated fields of a line being parsed.
+struct Fields<'a> {
+    line: usize,
+    tokens: SplitWhitespace<'a>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(line: usize, text: &'a str) -> Self {
+        Self {
+            line,
+            tokens: text.split_whitespace(),
+        }
+    }
+
+    fn error(&self, expected: &'static str, found: impl fmt::Display) -> ParseError {
+        ParseError {
+            line: self.line,
+            expected,
+            found: format!("`{found}`"),
+        }
+    }
+
+    fn next<T: FromStr>(&mut self, expected: &'static str) -> Result<T, ParseError> {
+        let Some(token) = self.tokens.next() else {
+            return Err(self.end(expected));
+        };
+        token.parse().map_err(|_| self.error(expected, token))
+    }
+
+    /// The error for a line ending where `expected` should be.
+    fn end(&self, expected: &'static str) -> ParseError {
+        ParseError {
+            line: self.line,
+            expected,
+            found: String::from("the end of the line"),
+        }
+    }
+
+    /// Fail unless the whole line has been read.
+    fn finish(&mut self) -> Result<(), ParseError> {
+        match self.tokens.next() {
+            Some(token) => Err(self.error("the end of the line", token)),
+            None => Ok(()),
+        }
+    }
+
+    fn car(&mut self) -> Result<CarId, ParseError> {
+        self.next("a car number")
+    }
+
+    fn direction(&mut self) -> Result<Direction, ParseError> {
+        const EXPECTED: &str = "`up` or `down`";
+        match self.next::<String>(EXPECTED)?.as_str() {
+            "up" => Ok(Direction::Up),
+            "down" => Ok(Direction::Down),
+            other => Err(self.error(EXPECTED, other)),
+        }
+    }
+}
+
+/// The lines of `text` worth parsing, numbered from one: blank lines and
+/// lines starting with `#` are skipped.
+fn lines(text: &str) -> impl Iterator<Item = Fields<'_>> {
+    (text.lines().enumerate())
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, text)| Fields::new(line, text))
+}
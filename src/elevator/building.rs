@@ -0,0 +1,422 @@
+//! The floors of a building, and which cars stop at which of them.
+//!
+//! A building can be described in a small text format, one setting per line:
+//!
+//! ```text
+//! # Two basements, no 13th floor, and a run past the shops on floors 1 to 4
+//! # without landings. The third car only serves the ground floor and the
+//! # top of the building.
+//! floors -2 20
+//! cars 3
+//! capacity 1000
+//! skip 13
+//! express 1..=4
+//! car 2 0 14..=20
+//! ```
+//!
+//! `floors` gives the lowest and the highest floor, and has to come first.
+//! There is one car unless `cars` says otherwise, and cars take any load
+//! unless `capacity` gives the most each can carry, in kilograms. A `car` line
+//! limits that car to the floors and ranges of floors listed. Blank lines and
+//! lines starting with `#` are skipped.
+//!
+//! A described building has at most [`MAX_FLOORS`] floors and [`MAX_CARS`]
+//! cars, which keeps the floor-by-floor checks of its events cheap.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use super::{Button, CarId, Direction, Event, Fields, Floor, Mode, ParseError, Weight, lines};
+
+/// The most floors [`Building::parse`] accepts, counting those without
+/// landings.
+pub const MAX_FLOORS: usize = 1000;
+
+/// The most cars [`Building::parse`] accepts.
+pub const MAX_CARS: usize = 100;
+
+/// An [`Event`] that cannot happen in the building.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildingError {
+    /// The floor is below the lowest or above the highest floor.
+    NoSuchFloor(Floor),
+
+    NoSuchCar(CarId),
+
+    /// No car stops at the floor, so it has no lobby buttons.
+    NoLanding(Floor),
+
+    /// The car does not stop at the floor, so it has no button for it.
+    NotServed {
+        car: CarId,
+        floor: Floor,
+    },
+
+    /// No car stopping at the floor goes any further this way, so there is
+    /// no button for it, like an up button on the top floor.
+    NoButton(Direction, Floor),
+}
+
+impl fmt::Display for BuildingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildingError::NoSuchFloor(floor) => write!(f, "there is no floor {floor}"),
+            BuildingError::NoSuchCar(car) => write!(f, "there is no car {car}"),
+            BuildingError::NoLanding(floor) => write!(f, "no car stops at floor {floor}"),
+            BuildingError::NotServed { car, floor } => {
+                write!(f, "car {car} does not stop at floor {floor}")
+            }
+            BuildingError::NoButton(direction, floor) => {
+                write!(f, "floor {floor} has no {direction} button")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildingError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Building {
+    pub lowest: Floor,
+    pub highest: Floor,
+    /// Floors without landings, like a missing 13th floor.
+    pub skipped: BTreeSet<Floor>,
+    /// Runs of floors the cars pass through without landings.
+    pub express: Vec<RangeInclusive<Floor>>,
+    /// For each car, the floors it is limited to, or `None` if it stops at
+    /// every landing.
+    pub cars: Vec<Option<BTreeSet<Floor>>>,
+    /// The most a car can carry, or `None` if there is no limit.
+    pub capacity: Option<Weight>,
+}
+
+impl Building {
+    /// A building with a landing on every floor from `lowest` to `highest`,
+    /// and `cars` cars stopping at all of them and taking any load.
+    ///
+    /// # Panics
+    ///
+    /// If `lowest` is above `highest`, or there are no cars.
+    pub fn new(lowest: Floor, highest: Floor, cars: usize) -> Self {
+        assert!(lowest <= highest, "the lowest floor is above the highest");
+        assert!(cars > 0, "a building needs at least one car");
+        Self {
+            lowest,
+            highest,
+            skipped: BTreeSet::new(),
+            express: vec![],
+            cars: vec![None; cars],
+            capacity: None,
+        }
+    }
+
+    /// Read a building from its text description.
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut lines = lines(text);
+        let Some(mut fields) = lines.next() else {
+            return Err(ParseError {
+                line: text.lines().count() + 1,
+                expected: "`floors`",
+                found: String::from("the end of the file"),
+            });
+        };
+        let setting: String = fields.next("`floors`")?;
+        if setting != "floors" {
+            return Err(fields.error("`floors`", setting));
+        }
+        let lowest = fields.next("the lowest floor")?;
+        let highest = fields.next("the highest floor")?;
+        if highest < lowest {
+            return Err(fields.error("a highest floor above the lowest", highest));
+        }
+        if i64::from(highest) - i64::from(lowest) >= MAX_FLOORS as i64 {
+            return Err(fields.error("at most 1000 floors", highest));
+        }
+        fields.finish()?;
+        let mut building = Building::new(lowest, highest, 1);
+
+        for mut fields in lines {
+            match fields.next::<String>("a setting")?.as_str() {
+                "cars" => {
+                    let cars = fields.next("a number of cars")?;
+                    if cars == 0 {
+                        return Err(fields.error("at least one car", cars));
+                    }
+                    if cars > MAX_CARS {
+                        return Err(fields.error("at most 100 cars", cars));
+                    }
+                    building.cars.resize(cars, None);
+                }
+                "capacity" => building.capacity = Some(fields.next("a weight")?),
+                "skip" => building.skipped.extend(building.floors(&mut fields)?),
+                "express" => {
+                    let range = building.range(&mut fields)?;
+                    building.express.push(range);
+                }
+                "car" => {
+                    let car = fields.car()?;
+                    if car >= building.cars.len() {
+                        return Err(fields.error("a car counted by `cars`", car));
+                    }
+                    building.cars[car] = Some(building.floors(&mut fields)?);
+                }
+                setting => return Err(fields.error("a setting", setting)),
+            }
+            fields.finish()?;
+        }
+        Ok(building)
+    }
+
+    /// Whether `floor` is one of the building's floors.
+    pub fn contains(&self, floor: Floor) -> bool {
+        (self.lowest..=self.highest).contains(&floor)
+    }
+
+    /// Whether cars can stop at `floor` at all.
+    pub fn has_landing(&self, floor: Floor) -> bool {
+        self.contains(floor)
+            && !self.skipped.contains(&floor)
+            && !self.express.iter().any(|range| range.contains(&floor))
+    }
+
+    /// Whether `car` stops at `floor`.
+    pub fn stops(&self, car: CarId, floor: Floor) -> bool {
+        self.has_landing(floor)
+            && (self.cars.get(car))
+                .is_some_and(|floors| floors.as_ref().is_none_or(|f| f.contains(&floor)))
+    }
+
+    /// The floors `car` stops at, from the lowest up.
+    pub fn stops_of(&self, car: CarId) -> impl Iterator<Item = Floor> + '_ {
+        (self.lowest..=self.highest).filter(move |&floor| self.stops(car, floor))
+    }
+
+    /// Check that `event` can happen in the building.
+    ///
+    /// Cars may arrive at floors they do not stop at, as they pass them, but
+    /// can only be recalled to floors they stop at.
+    pub fn validate(&self, event: Event) -> Result<(), BuildingError> {
+        match event {
+            Event::ButtonPressed(Button::LobbyCall(direction, floor)) => {
+                self.check_floor(floor)?;
+                let cars: Vec<_> = (0..self.cars.len())
+                    .filter(|&car| self.stops(car, floor))
+                    .collect();
+                if cars.is_empty() {
+                    return Err(BuildingError::NoLanding(floor));
+                }
+                let goes_further = |&car: &CarId| {
+                    (self.stops_of(car)).any(|other| direction.is_beyond(floor, other))
+                };
+                if !cars.iter().any(goes_further) {
+                    return Err(BuildingError::NoButton(direction, floor));
+                }
+                Ok(())
+            }
+            Event::ButtonPressed(Button::CarFloor(car, floor))
+            | Event::ModeChanged(car, Mode::FireRecall(floor)) => {
+                self.check_car(car)?;
+                self.check_floor(floor)?;
+                if !self.stops(car, floor) {
+                    return Err(BuildingError::NotServed { car, floor });
+                }
+                Ok(())
+            }
+            Event::CarArrived(car, floor) => {
+                self.check_car(car)?;
+                self.check_floor(floor)
+            }
+            Event::CarDoorOpened(car)
+            | Event::CarDoorClosed(car)
+            | Event::PassengerBoarded(car, _)
+            | Event::PassengerAlighted(car, _)
+            | Event::ModeChanged(car, _)
+            | Event::PowerRestored(car) => self.check_car(car),
+        }
+    }
+
+    fn check_car(&self, car: CarId) -> Result<(), BuildingError> {
+        if car >= self.cars.len() {
+            return Err(BuildingError::NoSuchCar(car));
+        }
+        Ok(())
+    }
+
+    fn check_floor(&self, floor: Floor) -> Result<(), BuildingError> {
+        if !self.contains(floor) {
+            return Err(BuildingError::NoSuchFloor(floor));
+        }
+        Ok(())
+    }
+
+    fn floor(&self, fields: &mut Fields<'_>, token: &str) -> Result<Floor, ParseError> {
+        const EXPECTED: &str = "a floor of the building";
+        match token.parse() {
+            Ok(floor) if self.contains(floor) => Ok(floor),
+            _ => Err(fields.error(EXPECTED, token)),
+        }
+    }
+
+    /// A range of floors written `from..=to`.
+    fn range(&self, fields: &mut Fields<'_>) -> Result<RangeInclusive<Floor>, ParseError> {
+        const EXPECTED: &str = "a range of floors";
+        let token: String = fields.next(EXPECTED)?;
+        let Some((from, to)) = token.split_once("..=") else {
+            return Err(fields.error(EXPECTED, token));
+        };
+        let range = self.floor(fields, from)?..=self.floor(fields, to)?;
+        if range.is_empty() {
+            return Err(fields.error(EXPECTED, token));
+        }
+        Ok(range)
+    }
+
+    /// The rest of the line: floors and ranges of floors, at least one.
+    fn floors(&self, fields: &mut Fields<'_>) -> Result<BTreeSet<Floor>, ParseError> {
+        let mut floors = BTreeSet::new();
+        let tokens: Vec<&str> = fields.tokens.by_ref().collect();
+        if tokens.is_empty() {
+            return Err(fields.end("a floor"));
+        }
+        for token in tokens {
+            if token.contains("..=") {
+                let mut range = Fields::new(fields.line, token);
+                floors.extend(self.range(&mut range)?);
+            } else {
+                floors.insert(self.floor(fields, token)?);
+            }
+        }
+        Ok(floors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::{
+        car_arrived, car_door_opened, car_floor_button_pressed, lobby_call_button_pressed,
+        mode_changed,
+    };
+
+    const EXAMPLE: &str = "\
+# Two basements, no 13th floor, and a run past the shops on floors 1 to 4
+floors -2 20
+cars 3
+capacity 1000
+skip 13
+express 1..=4
+car 2 0 14..=20
+";
+
+    #[test]
+    fn test_parse() {
+        let building = Building::parse(EXAMPLE).unwrap();
+        let mut expected = Building::new(-2, 20, 3);
+        expected.capacity = Some(1000);
+        expected.skipped.insert(13);
+        expected.express.push(1..=4);
+        expected.cars[2] = Some([0].into_iter().chain(14..=20).collect());
+        assert_eq!(building, expected);
+
+        let error = |text| Building::parse(text).unwrap_err().to_string();
+        assert_eq!(
+            error("# nothing\n"),
+            "line 2: expected `floors`, found the end of the file"
+        );
+        assert_eq!(
+            error("cars 2\nfloors 0 3"),
+            "line 1: expected `floors`, found `cars`"
+        );
+        assert_eq!(
+            error("floors 3 0"),
+            "line 1: expected a highest floor above the lowest, found `0`"
+        );
+        assert_eq!(
+            error("floors -2147483648 2147483647"),
+            "line 1: expected at most 1000 floors, found `2147483647`"
+        );
+        assert!(Building::parse("floors -500 499").is_ok());
+        assert_eq!(
+            error("floors 0 9\ncars 18446744073709551615"),
+            "line 2: expected at most 100 cars, found `18446744073709551615`"
+        );
+        assert_eq!(
+            error("floors 0 9\n\nskip 4 10"),
+            "line 3: expected a floor of the building, found `10`"
+        );
+        assert_eq!(
+            error("floors 0 9\nexpress 5..=3"),
+            "line 2: expected a range of floors, found `5..=3`"
+        );
+        assert_eq!(
+            error("floors 0 9\ncar 1 0 5"),
+            "line 2: expected a car counted by `cars`, found `1`"
+        );
+        assert_eq!(
+            error("floors 0 9\ncars 0"),
+            "line 2: expected at least one car, found `0`"
+        );
+        assert_eq!(
+            error("floors 0 9\ncar 0"),
+            "line 2: expected a floor, found the end of the line"
+        );
+        assert_eq!(
+            error("floors 0 9\ncapacity heavy"),
+            "line 2: expected a weight, found `heavy`"
+        );
+        assert_eq!(
+            error("floors 0 9\nlobby 0"),
+            "line 2: expected a setting, found `lobby`"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let building = Building::parse(EXAMPLE).unwrap();
+        assert!(building.stops(0, 12) && !building.stops(2, 12));
+        assert!(!building.stops(0, 13) && !building.stops(1, 3) && !building.stops(3, 0));
+        assert_eq!(
+            building.stops_of(2).collect::<Vec<_>>(),
+            [0, 14, 15, 16, 17, 18, 19, 20]
+        );
+
+        let ok = [
+            lobby_call_button_pressed(-2, Direction::Up),
+            lobby_call_button_pressed(0, Direction::Down),
+            car_floor_button_pressed(2, 14),
+            car_arrived(1, 13),
+            car_door_opened(2),
+        ];
+        for event in ok {
+            assert_eq!(building.validate(event), Ok(()), "{event:?}");
+        }
+        let errors = [
+            (car_arrived(0, 21), BuildingError::NoSuchFloor(21)),
+            (car_door_opened(3), BuildingError::NoSuchCar(3)),
+            (
+                lobby_call_button_pressed(2, Direction::Up),
+                BuildingError::NoLanding(2),
+            ),
+            (
+                car_floor_button_pressed(2, 5),
+                BuildingError::NotServed { car: 2, floor: 5 },
+            ),
+            (
+                mode_changed(2, Mode::FireRecall(-1)),
+                BuildingError::NotServed { car: 2, floor: -1 },
+            ),
+            (
+                lobby_call_button_pressed(-2, Direction::Down),
+                BuildingError::NoButton(Direction::Down, -2),
+            ),
+            (
+                lobby_call_button_pressed(20, Direction::Up),
+                BuildingError::NoButton(Direction::Up, 20),
+            ),
+        ];
+        for (event, error) in errors {
+            assert_eq!(building.validate(event), Err(error));
+        }
+    }
+}
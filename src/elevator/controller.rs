@@ -0,0 +1,918 @@
+//! Controllers for a bank of elevator cars.
+//!
+//! The [`ElevatorController`] is driven entirely by [`Event`]s and answers
+//! each with at most one [`Command`] for one of its cars. Each lobby call is
+//! assigned to exactly one car by a [`Dispatch`] strategy; from then on, a
+//! [`CarController`] per car serves its calls. It sends its car one floor at
+//! a time:
+//! every [`Command::Move`] is completed by the [`Event::CarArrived`] for the
+//! next floor, and only then does the controller decide whether to stop there
+//! or keep going. Calls are collected in the direction of travel before the
+//! car turns around.
+//!
+//! The controller asks for the door to close as soon as it has opened; how
+//! long the door stays open before closing is up to the car. Cars only stop
+//! at the floors the [`Building`] has them stop at, and events that cannot
+//! happen in the building are rejected.
+//!
+//! The controller keeps track of each car's load as passengers get on and
+//! off, and rejects any boarding that would take a car over its capacity. A
+//! car loaded to [`LOAD_BYPASS`] percent of its capacity passes its lobby
+//! calls by until it has room again.
+//!
+//! Only cars in [`Mode::Normal`] are given lobby calls; a call no such car
+//! can answer is not taken. A car switched to another mode, or whose power
+//! comes back, drops the calls it can no longer answer, so their buttons have
+//! to be pressed again. After a power cut the car is first sent to find the
+//! nearest floor with [`Command::FindFloor`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::building::{Building, BuildingError};
+use super::dispatch::Dispatch;
+use super::{Button, CarId, Direction, Event, Floor, Mode, Weight};
+
+/// The percentage of its capacity a car can be loaded to before it stops
+/// answering lobby calls.
+pub const LOAD_BYPASS: Weight = 80;
+
+/// An instruction for the car.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Move to the next floor in this direction.
+    Move(Direction),
+    OpenDoor,
+    CloseDoor,
+    /// Creep to the nearest floor the car stops at, its position being
+    /// unknown, and report arriving there.
+    FindFloor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Door {
+    Open,
+    Closed,
+}
+
+/// An [`Event`] the car could not have reported in the state the controller
+/// knows it to be in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControllerError {
+    /// The car arrived at a floor while its door was open.
+    ArrivedWithDoorOpen(Floor),
+
+    /// The car arrived at a floor without having been sent anywhere.
+    ArrivedWhileStopped(Floor),
+
+    /// The car arrived somewhere other than the next floor it was sent to.
+    WrongFloor {
+        expected: Floor,
+        found: Floor,
+    },
+
+    /// The door opened while the car was moving.
+    DoorOpenedWhileMoving,
+
+    /// The door opened without being told to.
+    DoorOpenedUnrequested,
+
+    DoorAlreadyOpen,
+    DoorAlreadyClosed,
+
+    /// A passenger got on or off while the door was closed.
+    PassengerWithDoorClosed,
+
+    /// A passenger got on, taking the car's load over its capacity.
+    OverCapacity {
+        load: Weight,
+        capacity: Weight,
+    },
+
+    /// More weight got off the car than was in it.
+    AlightedFromEmptyCar,
+
+    /// A passenger got on, taking the car's load past what a [`Weight`] can
+    /// hold.
+    LoadOverflow,
+
+    /// The event cannot happen in the building at all.
+    Building(BuildingError),
+}
+
+impl fmt::Display for ControllerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControllerError::ArrivedWithDoorOpen(floor) => {
+                write!(f, "car arrived at floor {floor} with its door open")
+            }
+            ControllerError::ArrivedWhileStopped(floor) => {
+                write!(f, "car arrived at floor {floor} without moving")
+            }
+            ControllerError::WrongFloor { expected, found } => {
+                write!(f, "car arrived at floor {found}, expected floor {expected}")
+            }
+            ControllerError::DoorOpenedWhileMoving => write!(f, "door opened while moving"),
+            ControllerError::DoorOpenedUnrequested => write!(f, "door opened unrequested"),
+            ControllerError::DoorAlreadyOpen => write!(f, "door opened but was already open"),
+            ControllerError::DoorAlreadyClosed => {
+                write!(f, "door closed but was already closed")
+            }
+            ControllerError::PassengerWithDoorClosed => {
+                write!(f, "passenger got on or off with the door closed")
+            }
+            ControllerError::OverCapacity { load, capacity } => {
+                write!(
+                    f,
+                    "car loaded to {load} kg, over its capacity of {capacity} kg"
+                )
+            }
+            ControllerError::AlightedFromEmptyCar => {
+                write!(f, "more weight got off the car than was in it")
+            }
+            ControllerError::LoadOverflow => {
+                write!(f, "car loaded to more than {} kg", Weight::MAX)
+            }
+            ControllerError::Building(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ControllerError {}
+
+/// Shares calls out between cars and tells each what to do next.
+pub struct ElevatorController {
+    building: Building,
+    cars: Vec<CarController>,
+    dispatch: Box<dyn Dispatch>,
+    /// The car each unanswered lobby call was assigned to.
+    assignments: BTreeMap<(Direction, Floor), CarId>,
+}
+
+impl ElevatorController {
+    /// A controller for the cars of `building`, idle and standing at
+    /// `floors` with their doors closed, the first car at the first floor and
+    /// so on.
+    ///
+    /// # Panics
+    ///
+    /// If there is not a floor of the building for every car.
+    pub fn new(building: Building, floors: &[Floor], dispatch: impl Dispatch + 'static) -> Self {
+        assert_eq!(floors.len(), building.cars.len(), "a floor for every car");
+        let car = |(car, &floor)| {
+            assert!(
+                building.contains(floor),
+                "car {car} is outside the building"
+            );
+            CarController::new(floor, building.stops_of(car), building.capacity)
+        };
+        Self {
+            cars: floors.iter().enumerate().map(car).collect(),
+            building,
+            dispatch: Box::new(dispatch),
+            assignments: BTreeMap::new(),
+        }
+    }
+
+    pub fn building(&self) -> &Building {
+        &self.building
+    }
+
+    pub fn cars(&self) -> &[CarController] {
+        &self.cars
+    }
+
+    /// # Panics
+    ///
+    /// If there is no such car.
+    pub fn car(&self, car: CarId) -> &CarController {
+        &self.cars[car]
+    }
+
+    /// The car answering the lobby call from `floor` for `direction`.
+    pub fn assignment(&self, direction: Direction, floor: Floor) -> Option<CarId> {
+        self.assignments.get(&(direction, floor)).copied()
+    }
+
+    /// Whether no car has anything left to do.
+    pub fn is_idle(&self) -> bool {
+        self.cars.iter().all(CarController::is_idle)
+    }
+
+    /// React to `event`, returning the next command for one of the cars, if
+    /// any.
+    ///
+    /// An event the car could not have reported is rejected, leaving the
+    /// controller as it was.
+    pub fn handle(&mut self, event: Event) -> Result<Option<(CarId, Command)>, ControllerError> {
+        (self.building.validate(event)).map_err(ControllerError::Building)?;
+        let car = match event {
+            Event::ButtonPressed(Button::LobbyCall(direction, floor)) => {
+                let answered = self.assignments.contains_key(&(direction, floor))
+                    || (self.cars.iter()).any(|car| car.is_boarding(direction, floor));
+                let answerable = (self.cars.iter()).any(|car| car.can_answer(floor));
+                if answered || !answerable {
+                    return Ok(None);
+                }
+                let car = self.dispatch.assign(direction, floor, &self.cars);
+                assert!(
+                    self.cars.get(car).is_some_and(|c| c.can_answer(floor)),
+                    "dispatch assigned a call to a car that cannot answer it"
+                );
+                self.assignments.insert((direction, floor), car);
+                car
+            }
+            _ => event.car().expect("every other event is about a car"),
+        };
+        let controller = &mut self.cars[car];
+        let command = controller.handle(event)?;
+        // Calls the car answered or dropped are no longer its to answer.
+        let calls = &controller.lobby_calls;
+        (self.assignments).retain(|call, assigned| *assigned != car || calls.contains(call));
+        Ok(command.map(|command| (car, command)))
+    }
+}
+
+/// Tracks one car and the calls it has to serve, and tells it what to do
+/// next.
+#[derive(Debug, Clone)]
+pub struct CarController {
+    floor: Floor,
+    /// The direction the car is serving calls in, kept while it stops.
+    direction: Option<Direction>,
+    door: Door,
+    /// The command the car is carrying out. The controller waits for the
+    /// event completing it before giving another.
+    pending: Option<Command>,
+    mode: Mode,
+    /// The floors the car stops at.
+    stops: BTreeSet<Floor>,
+    capacity: Option<Weight>,
+    /// The weight of the passengers in the car.
+    load: Weight,
+    car_calls: BTreeSet<Floor>,
+    lobby_calls: BTreeSet<(Direction, Floor)>,
+}
+
+impl CarController {
+    /// A controller for an idle, empty car standing at `floor` with its door
+    /// closed, which stops at `stops` and carries up to `capacity`, if given.
+    pub fn new(
+        floor: Floor,
+        stops: impl IntoIterator<Item = Floor>,
+        capacity: Option<Weight>,
+    ) -> Self {
+        Self {
+            floor,
+            mode: Mode::Normal,
+            stops: stops.into_iter().collect(),
+            capacity,
+            load: 0,
+            direction: None,
+            door: Door::Closed,
+            pending: None,
+            car_calls: BTreeSet::new(),
+            lobby_calls: BTreeSet::new(),
+        }
+    }
+
+    /// The floor the car is at, or last passed.
+    pub fn floor(&self) -> Floor {
+        self.floor
+    }
+
+    pub fn direction(&self) -> Option<Direction> {
+        self.direction
+    }
+
+    pub fn door(&self) -> Door {
+        self.door
+    }
+
+    pub fn pending(&self) -> Option<Command> {
+        self.pending
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Whether the car stops at `floor`.
+    pub fn serves(&self, floor: Floor) -> bool {
+        self.stops.contains(&floor)
+    }
+
+    /// Whether the car is in service and stops at `floor`, so a lobby call
+    /// there can be given to it.
+    pub fn can_answer(&self, floor: Floor) -> bool {
+        self.mode == Mode::Normal && self.serves(floor)
+    }
+
+    pub fn load(&self) -> Weight {
+        self.load
+    }
+
+    pub fn capacity(&self) -> Option<Weight> {
+        self.capacity
+    }
+
+    /// Whether a passenger of `weight` can get on without taking the car
+    /// over its capacity.
+    pub fn fits(&self, weight: Weight) -> bool {
+        self.load
+            .checked_add(weight)
+            .is_some_and(|load| self.capacity.is_none_or(|capacity| load <= capacity))
+    }
+
+    /// Whether the car is loaded to [`LOAD_BYPASS`] percent of its capacity,
+    /// and so passes lobby calls by.
+    pub fn is_full(&self) -> bool {
+        self.capacity.is_some_and(|capacity| {
+            u64::from(self.load) * 100 >= u64::from(capacity) * u64::from(LOAD_BYPASS)
+        })
+    }
+
+    /// Whether the car has nothing to do and no calls to serve.
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_none() && self.car_calls.is_empty() && self.lobby_calls.is_empty()
+    }
+
+    /// The floors pressed in the car that it has not stopped at yet.
+    pub fn car_calls(&self) -> impl Iterator<Item = Floor> + '_ {
+        self.car_calls.iter().copied()
+    }
+
+    /// The lobby calls assigned to the car that it has not answered yet.
+    pub fn lobby_calls(&self) -> impl Iterator<Item = (Direction, Floor)> + '_ {
+        self.lobby_calls.iter().copied()
+    }
+
+    /// The call furthest beyond the car's floor in `direction`.
+    pub fn furthest_call(&self, direction: Direction) -> Option<Floor> {
+        let lobby_calls = self.lobby_calls.iter().map(|&(_, floor)| floor);
+        (self.car_calls.iter().copied().chain(lobby_calls))
+            .filter(|&floor| direction.is_beyond(self.floor, floor))
+            .max_by_key(|&floor| floor.abs_diff(self.floor))
+    }
+
+    /// Whether passengers at `floor` who want to go `direction` can get on
+    /// right now.
+    pub fn is_boarding(&self, direction: Direction, floor: Floor) -> bool {
+        self.mode == Mode::Normal
+            && self.door == Door::Open
+            && floor == self.floor
+            && self.direction.is_none_or(|d| d == direction)
+    }
+
+    /// The car ids in `event` are taken to be this car's.
+    fn handle(&mut self, event: Event) -> Result<Option<Command>, ControllerError> {
+        match event {
+            Event::ButtonPressed(button) => self.press(button),
+            Event::CarArrived(_, floor) => {
+                if self.door == Door::Open {
+                    return Err(ControllerError::ArrivedWithDoorOpen(floor));
+                }
+                match self.pending {
+                    Some(Command::Move(direction)) => {
+                        let expected = direction.step(self.floor);
+                        if floor != expected {
+                            return Err(ControllerError::WrongFloor {
+                                expected,
+                                found: floor,
+                            });
+                        }
+                    }
+                    // The car is wherever it found itself to be.
+                    Some(Command::FindFloor) => {}
+                    _ => return Err(ControllerError::ArrivedWhileStopped(floor)),
+                }
+                self.floor = floor;
+                self.pending = None;
+            }
+            Event::CarDoorOpened(_) => {
+                match self.pending {
+                    _ if self.door == Door::Open => return Err(ControllerError::DoorAlreadyOpen),
+                    Some(Command::Move(_)) => return Err(ControllerError::DoorOpenedWhileMoving),
+                    Some(Command::OpenDoor) => {}
+                    _ => return Err(ControllerError::DoorOpenedUnrequested),
+                }
+                self.door = Door::Open;
+                self.serve();
+                self.pending = None;
+            }
+            Event::CarDoorClosed(_) => {
+                if self.door == Door::Closed {
+                    return Err(ControllerError::DoorAlreadyClosed);
+                }
+                self.door = Door::Closed;
+                self.pending = None;
+            }
+            Event::PassengerBoarded(_, weight) => {
+                if self.door == Door::Closed {
+                    return Err(ControllerError::PassengerWithDoorClosed);
+                }
+                let load = self.load.checked_add(weight);
+                let load = load.ok_or(ControllerError::LoadOverflow)?;
+                if let Some(capacity) = self.capacity
+                    && load > capacity
+                {
+                    return Err(ControllerError::OverCapacity { load, capacity });
+                }
+                self.load = load;
+                return Ok(None);
+            }
+            Event::PassengerAlighted(_, weight) => {
+                if self.door == Door::Closed {
+                    return Err(ControllerError::PassengerWithDoorClosed);
+                }
+                let load = self.load.checked_sub(weight);
+                self.load = load.ok_or(ControllerError::AlightedFromEmptyCar)?;
+                return Ok(None);
+            }
+            Event::ModeChanged(_, mode) => {
+                self.mode = mode;
+                match mode {
+                    Mode::Normal => {}
+                    Mode::Maintenance => self.lobby_calls.clear(),
+                    Mode::FireRecall(_) => {
+                        self.car_calls.clear();
+                        self.lobby_calls.clear();
+                    }
+                }
+            }
+            Event::PowerRestored(_) => {
+                self.door = Door::Closed;
+                self.direction = None;
+                self.car_calls.clear();
+                self.lobby_calls.clear();
+                self.pending = Some(Command::FindFloor);
+                return Ok(self.pending);
+            }
+        }
+        Ok(self.decide())
+    }
+
+    fn press(&mut self, button: Button) {
+        match button {
+            // Buttons do nothing while the car is recalled.
+            _ if matches!(self.mode, Mode::FireRecall(_)) => {}
+            // The door is open on this floor, so the call is answered already.
+            Button::CarFloor(_, floor) if self.door == Door::Open && floor == self.floor => {}
+            Button::LobbyCall(direction, floor) if self.is_boarding(direction, floor) => {}
+            Button::CarFloor(_, floor) => {
+                self.car_calls.insert(floor);
+            }
+            Button::LobbyCall(direction, floor) => {
+                self.lobby_calls.insert((direction, floor));
+            }
+        }
+    }
+
+    /// Whether any call lies beyond the car's floor in `direction`, not
+    /// counting lobby calls while the car is full.
+    fn calls_beyond(&self, direction: Direction) -> bool {
+        let beyond = |floor| direction.is_beyond(self.floor, floor);
+        self.car_calls.iter().any(|&floor| beyond(floor))
+            || (!self.is_full() && self.lobby_calls.iter().any(|&(_, floor)| beyond(floor)))
+    }
+
+    fn lobby_call_here(&self, direction: Direction) -> bool {
+        !self.is_full() && self.lobby_calls.contains(&(direction, self.floor))
+    }
+
+    /// Whether the car should open its door on the floor it is at: for
+    /// anyone getting off, anyone waiting to go its way, or, when there is
+    /// nothing further its way, anyone waiting at all.
+    fn should_stop(&self) -> bool {
+        if self.car_calls.contains(&self.floor) {
+            return true;
+        }
+        match self.direction {
+            Some(direction) if self.calls_beyond(direction) => self.lobby_call_here(direction),
+            _ => self.lobby_call_here(Direction::Up) || self.lobby_call_here(Direction::Down),
+        }
+    }
+
+    /// Clear the calls the open door answers, turning the car around if
+    /// it is only needed the other way.
+    fn serve(&mut self) {
+        self.car_calls.remove(&self.floor);
+        let direction = match self.direction {
+            Some(d) if self.calls_beyond(d) || self.lobby_call_here(d) => Some(d),
+            current => (current.map(Direction::reverse).into_iter())
+                .chain([Direction::Up, Direction::Down])
+                .find(|&d| self.lobby_call_here(d))
+                .or(current),
+        };
+        if let Some(direction) = direction {
+            self.lobby_calls.remove(&(direction, self.floor));
+        }
+        self.direction = direction;
+    }
+
+    /// The next command for a car that has finished the last one.
+    fn decide(&mut self) -> Option<Command> {
+        if self.pending.is_some() {
+            return None;
+        }
+        let recall = match self.mode {
+            Mode::FireRecall(floor) => Some(floor),
+            _ => None,
+        };
+        let command = if self.door == Door::Open {
+            // A recalled car holds its door open; any other closes it.
+            if recall == Some(self.floor) {
+                return None;
+            }
+            Command::CloseDoor
+        } else if let Some(floor) = recall {
+            if floor == self.floor {
+                Command::OpenDoor
+            } else {
+                let direction = if floor > self.floor {
+                    Direction::Up
+                } else {
+                    Direction::Down
+                };
+                self.direction = Some(direction);
+                Command::Move(direction)
+            }
+        } else if self.should_stop() {
+            Command::OpenDoor
+        } else {
+            let ahead = self.direction.filter(|&d| self.calls_beyond(d));
+            let other = (self.direction.map(Direction::reverse).into_iter())
+                .chain([Direction::Up, Direction::Down])
+                .find(|&d| self.calls_beyond(d));
+            self.direction = ahead.or(other);
+            Command::Move(self.direction?)
+        };
+        self.pending = Some(command);
+        self.pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::dispatch::NearestCar;
+    use crate::elevator::{
+        car_arrived, car_door_closed, car_door_opened, car_floor_button_pressed,
+        lobby_call_button_pressed, mode_changed, passenger_alighted, passenger_boarded,
+        power_restored,
+    };
+    use std::collections::VecDeque;
+
+    /// Carry out every command of `controller` the way its cars would,
+    /// pressing `presses` before the first one, and return the cars and
+    /// floors the doors opened at.
+    fn stops(controller: &mut ElevatorController, presses: &[Event]) -> Vec<(CarId, Floor)> {
+        let mut commands = VecDeque::new();
+        for &press in presses {
+            commands.extend(controller.handle(press).unwrap());
+        }
+        carry_out(controller, commands)
+    }
+
+    fn carry_out(
+        controller: &mut ElevatorController,
+        mut commands: VecDeque<(CarId, Command)>,
+    ) -> Vec<(CarId, Floor)> {
+        let mut stops = vec![];
+        while let Some((car, command)) = commands.pop_front() {
+            let event = match command {
+                Command::Move(direction) => {
+                    car_arrived(car, direction.step(controller.car(car).floor()))
+                }
+                Command::OpenDoor => {
+                    stops.push((car, controller.car(car).floor()));
+                    car_door_opened(car)
+                }
+                Command::CloseDoor => car_door_closed(car),
+                // The car finds itself level with the floor it was at.
+                Command::FindFloor => car_arrived(car, controller.car(car).floor()),
+            };
+            commands.extend(controller.handle(event).unwrap());
+        }
+        assert!(controller.is_idle());
+        stops
+    }
+
+    fn floors(stops: Vec<(CarId, Floor)>) -> Vec<Floor> {
+        stops.into_iter().map(|(_, floor)| floor).collect()
+    }
+
+    #[test]
+    fn test_serves_calls() {
+        let mut controller = ElevatorController::new(Building::new(-5, 10, 1), &[0], NearestCar);
+        let up = lobby_call_button_pressed(2, Direction::Up);
+        let expect = |command| Ok(Some((0, command)));
+        assert_eq!(controller.handle(up), expect(Command::Move(Direction::Up)));
+        assert_eq!(
+            controller.handle(car_arrived(0, 1)),
+            expect(Command::Move(Direction::Up))
+        );
+        assert_eq!(
+            controller.handle(car_arrived(0, 2)),
+            expect(Command::OpenDoor)
+        );
+        assert_eq!(
+            controller.handle(car_door_opened(0)),
+            expect(Command::CloseDoor)
+        );
+        assert_eq!(controller.car(0).door(), Door::Open);
+        assert_eq!(controller.handle(car_floor_button_pressed(0, 1)), Ok(None));
+        assert_eq!(controller.car(0).car_calls().collect::<Vec<_>>(), [1]);
+        assert_eq!(
+            controller.handle(car_door_closed(0)),
+            expect(Command::Move(Direction::Down))
+        );
+        assert_eq!(
+            controller.handle(car_arrived(0, 1)),
+            expect(Command::OpenDoor)
+        );
+        assert_eq!(
+            controller.handle(car_door_opened(0)),
+            expect(Command::CloseDoor)
+        );
+        assert_eq!(controller.handle(car_door_closed(0)), Ok(None));
+        assert!(controller.is_idle());
+
+        // A call where the car stands just opens the door.
+        let here = lobby_call_button_pressed(1, Direction::Down);
+        assert_eq!(stops(&mut controller, &[here]), [(0, 1)]);
+    }
+
+    #[test]
+    fn test_collects_calls_on_the_way() {
+        let mut controller = ElevatorController::new(Building::new(-5, 10, 1), &[0], NearestCar);
+        let presses = [
+            car_floor_button_pressed(0, 5),
+            lobby_call_button_pressed(3, Direction::Down),
+            lobby_call_button_pressed(2, Direction::Up),
+            lobby_call_button_pressed(-1, Direction::Up),
+        ];
+        assert_eq!(floors(stops(&mut controller, &presses)), [2, 5, 3, -1]);
+
+        // At the end of a run, a call the other way is answered in passing.
+        let presses = [
+            car_floor_button_pressed(0, 4),
+            lobby_call_button_pressed(4, Direction::Up),
+            lobby_call_button_pressed(6, Direction::Down),
+        ];
+        assert_eq!(floors(stops(&mut controller, &presses)), [4, 6]);
+        assert_eq!(controller.car(0).direction(), None);
+    }
+
+    #[test]
+    fn test_assigns_each_lobby_call_to_one_car() {
+        let mut controller = ElevatorController::new(Building::new(0, 10, 2), &[0, 10], NearestCar);
+        let down = lobby_call_button_pressed(8, Direction::Down);
+        let first = controller.handle(down).unwrap();
+        assert_eq!(first, Some((1, Command::Move(Direction::Down))));
+        assert_eq!(controller.handle(down), Ok(None));
+        assert_eq!(controller.assignment(Direction::Down, 8), Some(1));
+        assert_eq!(controller.car(0).lobby_calls().count(), 0);
+        assert_eq!(controller.car(1).lobby_calls().count(), 1);
+
+        let mut commands = VecDeque::from_iter(first);
+        let up = lobby_call_button_pressed(2, Direction::Up);
+        commands.extend(controller.handle(up).unwrap());
+        assert_eq!(controller.assignment(Direction::Up, 2), Some(0));
+        assert_eq!(carry_out(&mut controller, commands), [(1, 8), (0, 2)]);
+        assert_eq!(controller.assignment(Direction::Down, 8), None);
+    }
+
+    #[test]
+    fn test_stops_only_where_the_building_lets_it() {
+        let building = Building::parse("floors 0 10\ncars 2\nexpress 1..=5\ncar 1 0 8..=10");
+        let mut controller = ElevatorController::new(building.unwrap(), &[0, 10], NearestCar);
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(1, 7)),
+            Err(ControllerError::Building(BuildingError::NotServed {
+                car: 1,
+                floor: 7
+            }))
+        );
+        assert_eq!(
+            controller.handle(lobby_call_button_pressed(3, Direction::Up)),
+            Err(ControllerError::Building(BuildingError::NoLanding(3)))
+        );
+
+        // Car 1 is nearer, but does not stop at 6.
+        let presses = [
+            lobby_call_button_pressed(6, Direction::Down),
+            car_floor_button_pressed(1, 0),
+        ];
+        assert_eq!(stops(&mut controller, &presses), [(0, 6), (1, 0)]);
+        assert!(!controller.car(1).serves(6) && controller.car(0).serves(6));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut building = Building::new(0, 10, 1);
+        building.capacity = Some(500);
+        let mut controller = ElevatorController::new(building, &[0], NearestCar);
+        assert_eq!(
+            controller.handle(passenger_boarded(0, 80)),
+            Err(ControllerError::PassengerWithDoorClosed)
+        );
+        let up = lobby_call_button_pressed(0, Direction::Up);
+        assert_eq!(controller.handle(up), Ok(Some((0, Command::OpenDoor))));
+        controller.handle(car_door_opened(0)).unwrap();
+        controller.handle(passenger_boarded(0, 300)).unwrap();
+        assert_eq!(
+            controller.handle(passenger_boarded(0, 250)),
+            Err(ControllerError::OverCapacity {
+                load: 550,
+                capacity: 500
+            })
+        );
+        assert_eq!(
+            controller.handle(passenger_alighted(0, 301)),
+            Err(ControllerError::AlightedFromEmptyCar)
+        );
+        controller.handle(passenger_boarded(0, 120)).unwrap();
+        assert_eq!(controller.car(0).load(), 420);
+        assert!(controller.car(0).is_full() && !controller.car(0).fits(81));
+        assert!(!controller.car(0).fits(Weight::MAX));
+        assert_eq!(
+            controller.handle(passenger_boarded(0, Weight::MAX)),
+            Err(ControllerError::LoadOverflow)
+        );
+
+        // Full, the car passes the call at 4 by and comes back for it.
+        let presses = [
+            car_floor_button_pressed(0, 6),
+            lobby_call_button_pressed(4, Direction::Up),
+        ];
+        let mut commands = VecDeque::new();
+        for press in presses {
+            commands.extend(controller.handle(press).unwrap());
+        }
+        commands.extend(controller.handle(car_door_closed(0)).unwrap());
+        let mut stops = vec![];
+        while let Some((_, command)) = commands.pop_front() {
+            let event = match command {
+                Command::Move(direction) => {
+                    car_arrived(0, direction.step(controller.car(0).floor()))
+                }
+                Command::OpenDoor => {
+                    stops.push(controller.car(0).floor());
+                    car_door_opened(0)
+                }
+                Command::CloseDoor => {
+                    if controller.car(0).load() > 0 {
+                        controller.handle(passenger_alighted(0, 420)).unwrap();
+                    }
+                    car_door_closed(0)
+                }
+                Command::FindFloor => unreachable!("the power never went out"),
+            };
+            commands.extend(controller.handle(event).unwrap());
+        }
+        assert_eq!(stops, [6, 4]);
+    }
+
+    #[test]
+    fn test_huge_weights() {
+        let mut building = Building::new(0, 10, 1);
+        building.capacity = Some(Weight::MAX);
+        let mut controller = ElevatorController::new(building, &[0], NearestCar);
+        let up = lobby_call_button_pressed(0, Direction::Up);
+        assert_eq!(controller.handle(up), Ok(Some((0, Command::OpenDoor))));
+        controller.handle(car_door_opened(0)).unwrap();
+        controller
+            .handle(passenger_boarded(0, Weight::MAX / 2))
+            .unwrap();
+        assert!(!controller.car(0).is_full());
+        controller
+            .handle(passenger_boarded(0, Weight::MAX / 2))
+            .unwrap();
+        assert!(controller.car(0).is_full() && controller.car(0).fits(1));
+        controller.handle(passenger_boarded(0, 1)).unwrap();
+        assert_eq!(
+            controller.handle(passenger_boarded(0, 1)),
+            Err(ControllerError::LoadOverflow)
+        );
+    }
+
+    #[test]
+    fn test_modes() {
+        let mut controller = ElevatorController::new(Building::new(0, 10, 2), &[0, 5], NearestCar);
+        let expect = |car, command| Ok(Some((car, command)));
+
+        // A car in maintenance gets no lobby calls, but goes where it is sent.
+        assert_eq!(
+            controller.handle(mode_changed(1, Mode::Maintenance)),
+            Ok(None)
+        );
+        let up = lobby_call_button_pressed(6, Direction::Up);
+        assert_eq!(
+            controller.handle(up),
+            expect(0, Command::Move(Direction::Up))
+        );
+        assert_eq!(controller.assignment(Direction::Up, 6), Some(0));
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(1, 7)),
+            expect(1, Command::Move(Direction::Up))
+        );
+
+        // A recalled car drops its calls, goes to the floor and stays there
+        // with its door open, ignoring its buttons.
+        assert_eq!(
+            controller.handle(mode_changed(0, Mode::FireRecall(2))),
+            Ok(None)
+        );
+        assert_eq!(controller.assignment(Direction::Up, 6), None);
+        assert_eq!(
+            controller.handle(car_arrived(0, 1)),
+            expect(0, Command::Move(Direction::Up))
+        );
+        assert_eq!(
+            controller.handle(car_arrived(0, 2)),
+            expect(0, Command::OpenDoor)
+        );
+        assert_eq!(controller.handle(car_door_opened(0)), Ok(None));
+        assert_eq!(controller.handle(car_floor_button_pressed(0, 9)), Ok(None));
+        assert_eq!(controller.car(0).car_calls().count(), 0);
+        // With no car in service, nobody answers the call.
+        let down = lobby_call_button_pressed(3, Direction::Down);
+        assert_eq!(controller.handle(down), Ok(None));
+        assert_eq!(controller.assignment(Direction::Down, 3), None);
+        assert_eq!(
+            controller.handle(mode_changed(0, Mode::Normal)),
+            expect(0, Command::CloseDoor)
+        );
+
+        // Back from a power cut, a car forgets its calls and takes whatever
+        // floor it finds as where it is.
+        assert_eq!(
+            controller.handle(power_restored(1)),
+            expect(1, Command::FindFloor)
+        );
+        assert_eq!(controller.car(1).car_calls().count(), 0);
+        assert_eq!(controller.handle(car_arrived(1, 8)), Ok(None));
+        assert_eq!(controller.car(1).floor(), 8);
+        assert!(controller.car(1).is_idle());
+    }
+
+    #[test]
+    fn test_rejects_impossible_sequences() {
+        let mut controller = ElevatorController::new(Building::new(-5, 10, 1), &[0], NearestCar);
+        assert_eq!(
+            controller.handle(car_arrived(0, 1)),
+            Err(ControllerError::ArrivedWhileStopped(1))
+        );
+        assert_eq!(
+            controller.handle(car_door_opened(0)),
+            Err(ControllerError::DoorOpenedUnrequested)
+        );
+        assert_eq!(
+            controller.handle(car_door_closed(0)),
+            Err(ControllerError::DoorAlreadyClosed)
+        );
+        assert_eq!(
+            controller.handle(car_door_closed(1)),
+            Err(ControllerError::Building(BuildingError::NoSuchCar(1)))
+        );
+        assert_eq!(
+            controller.handle(car_floor_button_pressed(0, 11)),
+            Err(ControllerError::Building(BuildingError::NoSuchFloor(11)))
+        );
+
+        controller.handle(car_floor_button_pressed(0, 2)).unwrap();
+        assert_eq!(
+            controller.handle(car_arrived(0, -1)),
+            Err(ControllerError::WrongFloor {
+                expected: 1,
+                found: -1
+            })
+        );
+        assert_eq!(
+            controller.handle(car_door_opened(0)),
+            Err(ControllerError::DoorOpenedWhileMoving)
+        );
+        assert_eq!(controller.car(0).floor(), 0);
+        assert_eq!(
+            controller.car(0).pending(),
+            Some(Command::Move(Direction::Up))
+        );
+
+        controller.handle(car_arrived(0, 1)).unwrap();
+        controller.handle(car_arrived(0, 2)).unwrap();
+        controller.handle(car_door_opened(0)).unwrap();
+        assert_eq!(
+            controller.handle(car_arrived(0, 3)),
+            Err(ControllerError::ArrivedWithDoorOpen(3))
+        );
+        assert_eq!(
+            controller.handle(car_door_opened(0)),
+            Err(ControllerError::DoorAlreadyOpen)
+        );
+        assert_eq!(
+            ControllerError::ArrivedWithDoorOpen(3).to_string(),
+            "car arrived at floor 3 with its door open"
+        );
+    }
+}
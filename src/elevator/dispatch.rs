@@ -0,0 +1,154 @@
+//! Strategies for choosing which car answers a lobby call.
+//!
+//! The [`ElevatorController`](super::controller::ElevatorController) asks
+//! its strategy once per call, when the button is first pressed, and the call
+//! stays with the chosen car until that car answers it.
+
+use super::controller::CarController;
+use super::{CarId, Direction, Floor};
+
+pub trait Dispatch {
+    /// The car to answer a call from `floor` to go `direction`: the index of
+    /// one of `cars` that [can answer](CarController::can_answer) it, of
+    /// which there is always at least one.
+    fn assign(&mut self, direction: Direction, floor: Floor, cars: &[CarController]) -> CarId;
+}
+
+impl<D: Dispatch + ?Sized> Dispatch for Box<D> {
+    fn assign(&mut self, direction: Direction, floor: Floor, cars: &[CarController]) -> CarId {
+        (**self).assign(direction, floor, cars)
+    }
+}
+
+/// The car closest to the call, wherever it is heading.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NearestCar;
+
+impl Dispatch for NearestCar {
+    fn assign(&mut self, _: Direction, floor: Floor, cars: &[CarController]) -> CarId {
+        cheapest(cars, floor, |car| car.floor().abs_diff(floor))
+    }
+}
+
+/// The car that would reach the call first while sweeping (SCAN, or rather
+/// LOOK, as cars only go as far as their furthest call).
+///
+/// A car heading towards the call and going its way picks it up on the way.
+/// Any other car is counted as finishing its sweep first and then coming
+/// back, which is an estimate for one that would have to turn twice.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Look;
+
+impl Dispatch for Look {
+    fn assign(&mut self, direction: Direction, floor: Floor, cars: &[CarController]) -> CarId {
+        cheapest(cars, floor, |car| {
+            let here = car.floor();
+            match car.direction() {
+                Some(d) if d == direction && !d.is_beyond(floor, here) => here.abs_diff(floor),
+                Some(d) => {
+                    let end = (car.furthest_call(d).into_iter())
+                        .chain([floor, here])
+                        .filter(|&end| !d.is_beyond(end, here))
+                        .max_by_key(|end| end.abs_diff(here))
+                        .unwrap_or(here);
+                    here.abs_diff(end) + end.abs_diff(floor)
+                }
+                None => here.abs_diff(floor),
+            }
+        })
+    }
+}
+
+/// Every call to the next car in turn, wherever the cars are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RoundRobin {
+    next: CarId,
+}
+
+impl Dispatch for RoundRobin {
+    fn assign(&mut self, _: Direction, floor: Floor, cars: &[CarController]) -> CarId {
+        let car = (self.next..self.next + cars.len())
+            .map(|car| car % cars.len())
+            .find(|&car| cars[car].can_answer(floor))
+            .expect("some car can answer the call");
+        self.next = car + 1;
+        car
+    }
+}
+
+/// The first of the cars that can answer a call from `floor` that `cost`
+/// rates lowest.
+fn cheapest(cars: &[CarController], floor: Floor, cost: impl Fn(&CarController) -> u32) -> CarId {
+    (cars.iter().enumerate())
+        .filter(|(_, car)| car.can_answer(floor))
+        .min_by_key(|&(_, car)| cost(car))
+        .map(|(i, _)| i)
+        .expect("some car can answer the call")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::building::Building;
+    use crate::elevator::controller::ElevatorController;
+    use crate::elevator::{car_floor_button_pressed, lobby_call_button_pressed};
+
+    /// Cars at 0, 5 and 10, with car 1 on its way up to 8.
+    fn bank(dispatch: impl Dispatch + 'static) -> ElevatorController {
+        let building = Building::new(-2, 10, 3);
+        let mut controller = ElevatorController::new(building, &[0, 5, 10], dispatch);
+        controller.handle(car_floor_button_pressed(1, 8)).unwrap();
+        controller
+    }
+
+    fn assign(controller: &mut ElevatorController, direction: Direction, floor: Floor) -> CarId {
+        let call = lobby_call_button_pressed(floor, direction);
+        controller.handle(call).unwrap();
+        controller.assignment(direction, floor).unwrap()
+    }
+
+    #[test]
+    fn test_nearest_car() {
+        let mut controller = bank(NearestCar);
+        assert_eq!(assign(&mut controller, Direction::Down, 4), 1);
+        assert_eq!(assign(&mut controller, Direction::Up, 9), 2);
+        assert_eq!(assign(&mut controller, Direction::Up, -2), 0);
+    }
+
+    #[test]
+    fn test_look() {
+        let mut controller = bank(Look);
+        // Car 1 is nearest, but would have to get to 8 and come back.
+        assert_eq!(assign(&mut controller, Direction::Down, 4), 0);
+        // On its way up, car 1 picks this up first.
+        assert_eq!(assign(&mut controller, Direction::Up, 7), 1);
+        assert_eq!(assign(&mut controller, Direction::Down, 9), 2);
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut controller = bank(RoundRobin::default());
+        let cars: Vec<_> = (1..=5)
+            .map(|floor| assign(&mut controller, Direction::Up, floor))
+            .collect();
+        assert_eq!(cars, [0, 1, 2, 0, 1]);
+    }
+
+    #[test]
+    fn test_only_cars_serving_the_floor() {
+        let mut building = Building::new(0, 10, 3);
+        building.cars[0] = Some([0, 1, 2].into());
+        building.cars[2] = Some([0, 2, 4, 6, 8, 10].into());
+        let floors = [0, 5, 10];
+        let mut controller = ElevatorController::new(building.clone(), &floors, NearestCar);
+        assert_eq!(assign(&mut controller, Direction::Up, 9), 1);
+        assert_eq!(assign(&mut controller, Direction::Down, 2), 0);
+
+        let mut controller = ElevatorController::new(building, &floors, RoundRobin::default());
+        let cars: Vec<_> = [4, 3, 1]
+            .into_iter()
+            .map(|floor| assign(&mut controller, Direction::Up, floor))
+            .collect();
+        assert_eq!(cars, [1, 1, 0]);
+    }
+}
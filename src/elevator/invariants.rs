@@ -0,0 +1,433 @@
+//! Safety checks over a stream of [`Event`]s, such as a simulator run or a
+//! replayed [`log`](super::log).
+//!
+//! The checker only sees what the cars and buttons report, not what they
+//! were told to do, so it is independent of any controller. A car is level
+//! with a floor from when it arrives there, or from the start if its starting
+//! floor is given, and reporting an arrival means it has moved. A car whose
+//! power comes back is not level with any floor until it arrives at one, and
+//! forgets the floors pressed in it, as it does when it is recalled.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::building::Building;
+use super::{Button, CarId, Event, Floor, Mode, Weight};
+
+/// What went wrong.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// The car arrived at a floor with its door open.
+    MovedWithDoorOpen { car: CarId, floor: Floor },
+
+    /// The door opened while the car was not known to be level with a floor.
+    DoorOpenedBetweenFloors(CarId),
+
+    /// The door opened at a floor the building does not have the car stop
+    /// at, such as one in an express zone.
+    DoorOpenedWithoutLanding { car: CarId, floor: Floor },
+
+    /// A floor was pressed in the car, and the car never opened its door
+    /// there afterwards.
+    NeverServed { car: CarId, floor: Floor },
+
+    /// A car arrived at, or a button was pressed for, a floor outside the
+    /// building.
+    OutOfBounds(Floor),
+
+    /// Passengers got on a car until their weight was over its capacity.
+    Overloaded { car: CarId, load: Weight },
+
+    /// A passenger got on or off a car with its door closed.
+    PassengerWithDoorClosed(CarId),
+
+    /// A passenger got on a car that was not in normal service.
+    BoardedOutOfService(CarId),
+
+    /// The door of a recalled car opened at another floor than the one it
+    /// was recalled to, after the car had moved since the recall.
+    OpenedAwayFromRecall { car: CarId, floor: Floor },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::MovedWithDoorOpen { car, floor } => {
+                write!(f, "car {car} arrived at floor {floor} with its door open")
+            }
+            Problem::DoorOpenedBetweenFloors(car) => {
+                write!(f, "door of car {car} opened between floors")
+            }
+            Problem::DoorOpenedWithoutLanding { car, floor } => {
+                write!(
+                    f,
+                    "door of car {car} opened at floor {floor}, where it does not stop"
+                )
+            }
+            Problem::NeverServed { car, floor } => {
+                write!(f, "car {car} never stopped at floor {floor} as asked")
+            }
+            Problem::OutOfBounds(floor) => write!(f, "floor {floor} is outside the building"),
+            Problem::Overloaded { car, load } => {
+                write!(f, "car {car} was loaded to {load} kg, over its capacity")
+            }
+            Problem::PassengerWithDoorClosed(car) => {
+                write!(
+                    f,
+                    "a passenger got on or off car {car} with its door closed"
+                )
+            }
+            Problem::BoardedOutOfService(car) => {
+                write!(
+                    f,
+                    "a passenger got on car {car} while it was out of service"
+                )
+            }
+            Problem::OpenedAwayFromRecall { car, floor } => {
+                write!(
+                    f,
+                    "door of car {car} opened at floor {floor} while it was recalled elsewhere"
+                )
+            }
+        }
+    }
+}
+
+/// A [`Problem`] and the position of the event that shows it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// The position of the event in the stream, counting from zero.
+    pub index: usize,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "event {}: {}", self.index, self.problem)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Car {
+    /// The floor the car is level with, if known.
+    floor: Option<Floor>,
+    door_open: bool,
+    load: Weight,
+    mode: Mode,
+    /// Whether the car has arrived anywhere since its mode last changed.
+    moved: bool,
+}
+
+/// Checks events one at a time, collecting every violation.
+#[derive(Debug, Clone)]
+pub struct Checker {
+    building: Building,
+    cars: BTreeMap<CarId, Car>,
+    /// The first unserved press of each floor button in each car.
+    presses: BTreeMap<(CarId, Floor), usize>,
+    index: usize,
+    violations: Vec<Violation>,
+}
+
+impl Checker {
+    /// A checker for `building`, whose first cars start at `floors` with
+    /// their doors closed. Any other car is not known to be at any floor
+    /// until it arrives at one.
+    pub fn new(building: Building, floors: &[Floor]) -> Self {
+        let start = |&floor| Car {
+            floor: Some(floor),
+            ..Car::default()
+        };
+        let cars = floors.iter().map(start).enumerate().collect();
+        Self {
+            building,
+            cars,
+            presses: BTreeMap::new(),
+            index: 0,
+            violations: vec![],
+        }
+    }
+
+    /// Check the next event of the stream.
+    pub fn event(&mut self, event: Event) {
+        let floor = match event {
+            Event::ButtonPressed(Button::LobbyCall(_, floor)) => Some(floor),
+            Event::ButtonPressed(Button::CarFloor(car, floor)) => {
+                // A recalled car rightly ignores its buttons.
+                if !self.is_recalled(car) {
+                    self.presses.entry((car, floor)).or_insert(self.index);
+                }
+                Some(floor)
+            }
+            Event::CarArrived(car, floor) => {
+                let state = self.cars.entry(car).or_default();
+                state.floor = Some(floor);
+                state.moved = true;
+                if state.door_open {
+                    self.violate(Problem::MovedWithDoorOpen { car, floor });
+                }
+                Some(floor)
+            }
+            Event::CarDoorOpened(car) => {
+                let state = self.cars.entry(car).or_default();
+                state.door_open = true;
+                let recall = match state.mode {
+                    Mode::FireRecall(recall) if state.moved => Some(recall),
+                    _ => None,
+                };
+                match state.floor {
+                    Some(floor) if !self.building.stops(car, floor) => {
+                        self.violate(Problem::DoorOpenedWithoutLanding { car, floor });
+                    }
+                    Some(floor) if recall.is_some_and(|recall| recall != floor) => {
+                        self.violate(Problem::OpenedAwayFromRecall { car, floor });
+                    }
+                    Some(floor) => _ = self.presses.remove(&(car, floor)),
+                    None => self.violate(Problem::DoorOpenedBetweenFloors(car)),
+                }
+                None
+            }
+            Event::CarDoorClosed(car) => {
+                self.cars.entry(car).or_default().door_open = false;
+                None
+            }
+            Event::ModeChanged(car, mode) => {
+                let state = self.cars.entry(car).or_default();
+                state.mode = mode;
+                state.moved = false;
+                match mode {
+                    Mode::FireRecall(floor) => {
+                        self.forget_presses(car);
+                        Some(floor)
+                    }
+                    Mode::Normal | Mode::Maintenance => None,
+                }
+            }
+            Event::PowerRestored(car) => {
+                let state = self.cars.entry(car).or_default();
+                state.floor = None;
+                state.door_open = false;
+                self.forget_presses(car);
+                None
+            }
+            Event::PassengerBoarded(car, weight) | Event::PassengerAlighted(car, weight) => {
+                let state = self.cars.entry(car).or_default();
+                if let Event::PassengerBoarded(..) = event {
+                    state.load = state.load.saturating_add(weight);
+                } else {
+                    state.load = state.load.saturating_sub(weight);
+                }
+                let (door_open, load, mode) = (state.door_open, state.load, state.mode);
+                if !door_open {
+                    self.violate(Problem::PassengerWithDoorClosed(car));
+                }
+                if mode != Mode::Normal && matches!(event, Event::PassengerBoarded(..)) {
+                    self.violate(Problem::BoardedOutOfService(car));
+                }
+                if self
+                    .building
+                    .capacity
+                    .is_some_and(|capacity| load > capacity)
+                {
+                    self.violate(Problem::Overloaded { car, load });
+                }
+                None
+            }
+        };
+        if let Some(floor) = floor
+            && !self.building.contains(floor)
+        {
+            self.violate(Problem::OutOfBounds(floor));
+        }
+        self.index += 1;
+    }
+
+    /// The violations found, in the order of the events showing them,
+    /// counting presses still unserved as never served.
+    pub fn finish(mut self) -> Vec<Violation> {
+        for ((car, floor), index) in self.presses {
+            let problem = Problem::NeverServed { car, floor };
+            self.violations.push(Violation { index, problem });
+        }
+        self.violations.sort_by_key(|violation| violation.index);
+        self.violations
+    }
+
+    fn is_recalled(&self, car: CarId) -> bool {
+        (self.cars.get(&car)).is_some_and(|state| matches!(state.mode, Mode::FireRecall(_)))
+    }
+
+    /// Drop the presses of `car`, which the controller rightly forgot.
+    fn forget_presses(&mut self, car: CarId) {
+        self.presses.retain(|&(pressed, _), _| pressed != car);
+    }
+
+    fn violate(&mut self, problem: Problem) {
+        let index = self.index;
+        self.violations.push(Violation { index, problem });
+    }
+}
+
+/// Check `events` for `building` whose cars start at `floors`; see
+/// [`Checker::new`].
+pub fn check(
+    events: impl IntoIterator<Item = Event>,
+    building: &Building,
+    floors: &[Floor],
+) -> Vec<Violation> {
+    let mut checker = Checker::new(building.clone(), floors);
+    for event in events {
+        checker.event(event);
+    }
+    checker.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::controller::ElevatorController;
+    use crate::elevator::dispatch::{Look, NearestCar, RoundRobin};
+    use crate::elevator::simulator::{Profile, Simulator, Timing, Traffic};
+    use crate::elevator::{
+        Direction, car_arrived, car_door_closed, car_door_opened, car_floor_button_pressed,
+        lobby_call_button_pressed, log, mode_changed, passenger_alighted, passenger_boarded,
+        power_restored,
+    };
+
+    #[test]
+    fn test_simulations_are_safe() {
+        let building = Building::parse(
+            "floors -3 15\ncars 3\ncapacity 300\nskip 13\nexpress 2..=5\ncar 2 -3 0 10..=15",
+        );
+        let building = building.unwrap();
+        let traffic = Traffic {
+            passengers: 300,
+            mean_interval: 4,
+            profile: Profile::InterFloor,
+        };
+        let arrivals = traffic.arrivals(&building, 19).unwrap();
+        let floors = [0, 0, 10];
+        for controller in [
+            ElevatorController::new(building.clone(), &floors, NearestCar),
+            ElevatorController::new(building.clone(), &floors, Look),
+            ElevatorController::new(building.clone(), &floors, RoundRobin::default()),
+        ] {
+            let report = Simulator::new(Timing::default(), controller, &arrivals).run();
+            let events = report.log.iter().map(|&(_, event)| event);
+            assert_eq!(check(events, &building, &floors), []);
+
+            // And so are their logs, read back.
+            let replayed = log::parse(&log::to_text(&report.log)).unwrap();
+            let events = replayed.into_iter().map(|(_, event)| event);
+            assert_eq!(check(events, &building, &floors), []);
+        }
+    }
+
+    #[test]
+    fn test_modes_are_safe() {
+        let building = Building::parse("floors -3 15\ncars 3\ncapacity 300\ncar 2 -3 0 10..=15");
+        let building = building.unwrap();
+        let traffic = Traffic {
+            passengers: 300,
+            mean_interval: 4,
+            profile: Profile::InterFloor,
+        };
+        let arrivals = traffic.arrivals(&building, 23).unwrap();
+        let floors = [0, 0, 10];
+        let controller = || ElevatorController::new(building.clone(), &floors, Look);
+        let mut simulator = Simulator::new(Timing::default(), controller(), &arrivals);
+        simulator.inject(100, mode_changed(1, Mode::Maintenance));
+        simulator.inject(400, mode_changed(1, Mode::Normal));
+        for car in 0..3 {
+            simulator.inject(300, mode_changed(car, Mode::FireRecall(0)));
+            simulator.inject(500, mode_changed(car, Mode::Normal));
+        }
+        simulator.inject(301, power_restored(2));
+        simulator.inject(650, power_restored(0));
+        let report = simulator.run();
+        assert!(report.trips.iter().all(|trip| trip.alighted.is_some()));
+        let events = report.log.iter().map(|&(_, event)| event);
+        assert_eq!(check(events, &building, &floors), []);
+
+        // The controller takes the log as it was.
+        let mut controller = controller();
+        log::replay(&report.log, &mut controller).unwrap();
+        assert!(controller.is_idle());
+    }
+
+    #[test]
+    fn test_violations() {
+        let events = [
+            car_floor_button_pressed(0, 2),
+            car_door_opened(1),
+            car_arrived(0, 1),
+            car_door_opened(0),
+            car_floor_button_pressed(0, 3),
+            car_arrived(0, 2),
+            car_door_closed(0),
+            lobby_call_button_pressed(12, Direction::Down),
+            car_arrived(1, -1),
+            car_door_closed(1),
+            car_arrived(0, 3),
+            car_door_opened(0),
+            passenger_boarded(1, 90),
+            passenger_boarded(0, 500),
+            passenger_boarded(0, 600),
+            passenger_alighted(0, 600),
+            passenger_boarded(0, 100),
+            car_door_closed(0),
+            mode_changed(1, Mode::FireRecall(5)),
+            car_floor_button_pressed(1, 8),
+            car_arrived(1, 4),
+            car_door_opened(1),
+            passenger_boarded(1, 50),
+            power_restored(1),
+            car_door_opened(1),
+            mode_changed(1, Mode::FireRecall(20)),
+        ];
+        let mut building = Building::new(0, 10, 2);
+        building.skipped.insert(3);
+        building.capacity = Some(1000);
+        let violations: Vec<_> = (check(events, &building, &[0]).iter())
+            .map(Violation::to_string)
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                "event 0: car 0 never stopped at floor 2 as asked",
+                "event 1: door of car 1 opened between floors",
+                "event 4: car 0 never stopped at floor 3 as asked",
+                "event 5: car 0 arrived at floor 2 with its door open",
+                "event 7: floor 12 is outside the building",
+                "event 8: car 1 arrived at floor -1 with its door open",
+                "event 8: floor -1 is outside the building",
+                "event 11: door of car 0 opened at floor 3, where it does not stop",
+                "event 12: a passenger got on or off car 1 with its door closed",
+                "event 14: car 0 was loaded to 1100 kg, over its capacity",
+                "event 21: door of car 1 opened at floor 4 while it was recalled elsewhere",
+                "event 22: a passenger got on car 1 while it was out of service",
+                "event 24: door of car 1 opened between floors",
+                "event 25: floor 20 is outside the building",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_huge_loads() {
+        let replayed =
+            log::parse("0 door-opened 0\n1 boarded 0 4294967295\n2 boarded 0 1").unwrap();
+        let events = replayed.into_iter().map(|(_, event)| event);
+        let mut building = Building::new(0, 10, 1);
+        building.capacity = Some(1000);
+        let violations: Vec<_> = (check(events, &building, &[0]).iter())
+            .map(Violation::to_string)
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                "event 1: car 0 was loaded to 4294967295 kg, over its capacity",
+                "event 2: car 0 was loaded to 4294967295 kg, over its capacity",
+            ]
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! A line-based text format for timestamped [`Event`]s, so a run of the
+//! simulator can be saved and replayed against a controller later.
+//!
+//! Each line holds a timestamp, the kind of event and its fields, separated
+//! by whitespace:
+//!
+//! ```text
+//! # time kind        fields
+//! 5      lobby-call  up 0
+//! 6      door-opened 0
+//! 6      boarded     0 75
+//! 6      car-floor   0 3
+//! 11     arrived     0 1
+//! 21     door-closed 0
+//! ```
+//!
+//! Cars are given by number, directions as `up` or `down`, and the weights
+//! of passengers in kilograms. A `mode` line switches a car to `normal`,
+//! `maintenance` or `fire-recall` to a floor, and `power-restored` gives the
+//! car whose power came back. Timestamps never decrease. Blank lines and
+//! lines starting with `#` are skipped.
+
+use std::fmt;
+
+use super::controller::{Command, ControllerError, ElevatorController};
+use super::simulator::Time;
+use super::{Button, CarId, Event, Mode, ParseError, lines};
+
+/// The controller rejected an event of a log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayError {
+    /// The position of the event in the log, counting from zero.
+    pub index: usize,
+    pub time: Time,
+    pub error: ControllerError,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "event {} at {}: {}", self.index, self.time, self.error)
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// `log` in the text format, a line per event.
+pub fn to_text(log: &[(Time, Event)]) -> String {
+    let line = |(time, event): &(Time, Event)| {
+        let fields = match event {
+            Event::ButtonPressed(Button::LobbyCall(direction, floor)) => {
+                format!("lobby-call {direction} {floor}")
+            }
+            Event::ButtonPressed(Button::CarFloor(car, floor)) => {
+                format!("car-floor {car} {floor}")
+            }
+            Event::CarArrived(car, floor) => format!("arrived {car} {floor}"),
+            Event::CarDoorOpened(car) => format!("door-opened {car}"),
+            Event::CarDoorClosed(car) => format!("door-closed {car}"),
+            Event::PassengerBoarded(car, weight) => format!("boarded {car} {weight}"),
+            Event::PassengerAlighted(car, weight) => format!("alighted {car} {weight}"),
+            Event::ModeChanged(car, Mode::Normal) => format!("mode {car} normal"),
+            Event::ModeChanged(car, Mode::Maintenance) => format!("mode {car} maintenance"),
+            Event::ModeChanged(car, Mode::FireRecall(floor)) => {
+                format!("mode {car} fire-recall {floor}")
+            }
+            Event::PowerRestored(car) => format!("power-restored {car}"),
+        };
+        format!("{time} {fields}\n")
+    };
+    log.iter().map(line).collect()
+}
+
+/// Read a log back from its text format.
+pub fn parse(text: &str) -> Result<Vec<(Time, Event)>, ParseError> {
+    let mut log = vec![];
+    let mut last = 0;
+    for mut fields in lines(text) {
+        let time = fields.next("a timestamp")?;
+        if time < last {
+            return Err(fields.error("a timestamp no earlier than the last", time));
+        }
+        last = time;
+        let event = match fields.next::<String>("an event kind")?.as_str() {
+            "lobby-call" => {
+                let direction = fields.direction()?;
+                Event::ButtonPressed(Button::LobbyCall(direction, fields.next("a floor")?))
+            }
+            "car-floor" => {
+                let car = fields.car()?;
+                Event::ButtonPressed(Button::CarFloor(car, fields.next("a floor")?))
+            }
+            "arrived" => Event::CarArrived(fields.car()?, fields.next("a floor")?),
+            "door-opened" => Event::CarDoorOpened(fields.car()?),
+            "door-closed" => Event::CarDoorClosed(fields.car()?),
+            "boarded" => Event::PassengerBoarded(fields.car()?, fields.next("a weight")?),
+            "alighted" => Event::PassengerAlighted(fields.car()?, fields.next("a weight")?),
+            "mode" => {
+                let car = fields.car()?;
+                const EXPECTED: &str = "`normal`, `maintenance` or `fire-recall`";
+                let mode = match fields.next::<String>(EXPECTED)?.as_str() {
+                    "normal" => Mode::Normal,
+                    "maintenance" => Mode::Maintenance,
+                    "fire-recall" => Mode::FireRecall(fields.next("a floor")?),
+                    mode => return Err(fields.error(EXPECTED, mode)),
+                };
+                Event::ModeChanged(car, mode)
+            }
+            "power-restored" => Event::PowerRestored(fields.car()?),
+            kind => return Err(fields.error("an event kind", kind)),
+        };
+        fields.finish()?;
+        log.push((time, event));
+    }
+    Ok(log)
+}
+
+/// Feed the events of `log` to `controller` in order, returning the command
+/// it gave for each.
+pub fn replay(
+    log: &[(Time, Event)],
+    controller: &mut ElevatorController,
+) -> Result<Vec<Option<(CarId, Command)>>, ReplayError> {
+    (log.iter().enumerate())
+        .map(|(index, &(time, event))| {
+            (controller.handle(event)).map_err(|error| ReplayError { index, time, error })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::Direction;
+    use crate::elevator::building::Building;
+    use crate::elevator::dispatch::Look;
+    use crate::elevator::simulator::{Profile, Simulator, Timing, Traffic};
+
+    #[test]
+    fn test_round_trip() {
+        let log = vec![
+            (
+                0,
+                Event::ButtonPressed(Button::LobbyCall(Direction::Up, -2)),
+            ),
+            (
+                0,
+                Event::ButtonPressed(Button::LobbyCall(Direction::Down, 7)),
+            ),
+            (3, Event::ButtonPressed(Button::CarFloor(1, 4))),
+            (5, Event::CarArrived(0, -1)),
+            (8, Event::CarDoorOpened(12)),
+            (13, Event::CarDoorClosed(0)),
+            (13, Event::PassengerBoarded(0, 80)),
+            (14, Event::PassengerAlighted(3, 65)),
+            (20, Event::ModeChanged(1, Mode::FireRecall(-1))),
+            (20, Event::ModeChanged(1, Mode::Maintenance)),
+            (21, Event::ModeChanged(1, Mode::Normal)),
+            (30, Event::PowerRestored(2)),
+        ];
+        let text = to_text(&log);
+        assert_eq!(
+            text,
+            "0 lobby-call up -2\n0 lobby-call down 7\n3 car-floor 1 4\n5 arrived 0 -1\n\
+             8 door-opened 12\n13 door-closed 0\n13 boarded 0 80\n14 alighted 3 65\n\
+             20 mode 1 fire-recall -1\n20 mode 1 maintenance\n21 mode 1 normal\n\
+             30 power-restored 2\n"
+        );
+        assert_eq!(parse(&text), Ok(log));
+        assert_eq!(
+            parse("# a comment\n\n  4   arrived\t2  3  \n"),
+            Ok(vec![(4, Event::CarArrived(2, 3))])
+        );
+    }
+
+    #[test]
+    fn test_malformed_lines() {
+        let error = |text| parse(text).unwrap_err().to_string();
+        assert_eq!(
+            error("1 door-opened 0\n\n# skipped\nsoon door-closed 0"),
+            "line 4: expected a timestamp, found `soon`"
+        );
+        assert_eq!(
+            error("1 lift 0"),
+            "line 1: expected an event kind, found `lift`"
+        );
+        assert_eq!(
+            error("1 lobby-call sideways 3"),
+            "line 1: expected `up` or `down`, found `sideways`"
+        );
+        assert_eq!(
+            error("1 car-floor -1 3"),
+            "line 1: expected a car number, found `-1`"
+        );
+        assert_eq!(
+            error("1 arrived 0"),
+            "line 1: expected a floor, found the end of the line"
+        );
+        assert_eq!(
+            error("1 boarded 0 -70"),
+            "line 1: expected a weight, found `-70`"
+        );
+        assert_eq!(
+            error("1 mode 0 fire-recall"),
+            "line 1: expected a floor, found the end of the line"
+        );
+        assert_eq!(
+            error("1 mode 0 sleep"),
+            "line 1: expected `normal`, `maintenance` or `fire-recall`, found `sleep`"
+        );
+        assert_eq!(
+            error("1 door-closed 0 0"),
+            "line 1: expected the end of the line, found `0`"
+        );
+        assert_eq!(
+            error("2 door-opened 0\n1 door-closed 0"),
+            "line 2: expected a timestamp no earlier than the last, found `1`"
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let traffic = Traffic {
+            passengers: 50,
+            mean_interval: 5,
+            profile: Profile::InterFloor,
+        };
+        let building = Building::new(0, 12, 2);
+        let arrivals = traffic.arrivals(&building, 7).unwrap();
+        let controller = ElevatorController::new(building.clone(), &[0, 0], Look);
+        let report = Simulator::new(Timing::default(), controller, &arrivals).run();
+        let log = parse(&to_text(&report.log)).unwrap();
+        assert_eq!(log, report.log);
+
+        let mut controller = ElevatorController::new(building.clone(), &[0, 0], Look);
+        let commands = replay(&log, &mut controller).unwrap();
+        assert_eq!(commands.len(), log.len());
+        assert!(controller.is_idle());
+
+        // Dropping an arrival makes the next one impossible.
+        let i = (log.iter()).position(|(_, e)| matches!(e, Event::CarArrived(..)));
+        let mut broken = log.clone();
+        broken.remove(i.unwrap());
+        let mut controller = ElevatorController::new(building, &[0, 0], Look);
+        let error = replay(&broken, &mut controller).unwrap_err();
+        assert!(
+            matches!(error.error, ControllerError::WrongFloor { .. }),
+            "{error}"
+        );
+    }
+}
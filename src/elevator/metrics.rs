@@ -0,0 +1,260 @@
+//! Figures to judge a [`simulator`](super::simulator) run by, and to compare
+//! [`dispatch`](super::dispatch) strategies with.
+//!
+//! How long passengers wait and ride is read from the trips of a
+//! [`Report`]; how far the cars go, how often they turn around and how often
+//! their doors open, from its log. Floors travelled and reversals stand in
+//! for the energy a run takes, as climbing, stopping and starting again are
+//! what cost the most.
+
+use std::cmp::Ordering;
+
+use super::simulator::{Report, Time};
+use super::{Direction, Event, Floor};
+
+/// The spread of a set of durations.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    pub median: Time,
+    /// The 90th percentile.
+    pub p90: Time,
+    pub max: Time,
+}
+
+impl Summary {
+    /// The summary of `samples`, all zero if there are none.
+    pub fn of(samples: impl IntoIterator<Item = Time>) -> Self {
+        let mut samples: Vec<Time> = samples.into_iter().collect();
+        samples.sort_unstable();
+        let Some(&max) = samples.last() else {
+            return Self::default();
+        };
+        let count = samples.len();
+        Self {
+            count,
+            mean: samples.iter().sum::<Time>() as f64 / count as f64,
+            median: percentile(&samples, 50),
+            p90: percentile(&samples, 90),
+            max,
+        }
+    }
+}
+
+/// The smallest of the `sorted` samples that at least `percent` of them are
+/// no greater than.
+fn percentile(sorted: &[Time], percent: usize) -> Time {
+    let rank = (sorted.len() * percent).div_ceil(100);
+    sorted[rank.max(1) - 1]
+}
+
+/// How well a run went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    /// How many passengers turned up.
+    pub passengers: usize,
+    /// How long passengers waited for a car, of those who got on one.
+    pub wait: Summary,
+    /// How long passengers spent in the car, of those who got off.
+    pub ride: Summary,
+    /// How many floors the cars travelled altogether.
+    pub floors_travelled: u64,
+    /// How many times a car set off the other way from how it last moved.
+    pub reversals: usize,
+    /// How many times a car door opened.
+    pub door_cycles: usize,
+}
+
+/// The metrics of `report`, a run whose cars started at `floors`.
+///
+/// # Panics
+///
+/// If there is not a floor for every car in the log.
+pub fn collect(report: &Report, floors: &[Floor]) -> Metrics {
+    let mut cars: Vec<(Floor, Option<Direction>)> =
+        floors.iter().map(|&floor| (floor, None)).collect();
+    let (mut floors_travelled, mut reversals, mut door_cycles) = (0, 0, 0);
+    for &(_, event) in &report.log {
+        match event {
+            Event::CarArrived(car, floor) => {
+                let (at, heading) = &mut cars[car];
+                floors_travelled += u64::from(at.abs_diff(floor));
+                let direction = match floor.cmp(at) {
+                    Ordering::Greater => Some(Direction::Up),
+                    Ordering::Less => Some(Direction::Down),
+                    // A car finding itself level after a power cut.
+                    Ordering::Equal => None,
+                };
+                if let Some(direction) = direction {
+                    if heading.is_some_and(|heading| heading != direction) {
+                        reversals += 1;
+                    }
+                    *heading = Some(direction);
+                }
+                *at = floor;
+            }
+            Event::CarDoorOpened(_) => door_cycles += 1,
+            _ => {}
+        }
+    }
+    Metrics {
+        passengers: report.trips.len(),
+        wait: Summary::of(report.trips.iter().filter_map(|trip| trip.wait())),
+        ride: Summary::of(report.trips.iter().filter_map(|trip| trip.ride())),
+        floors_travelled,
+        reversals,
+        door_cycles,
+    }
+}
+
+/// A table comparing the metrics of runs with different dispatch
+/// strategies, a row per strategy, with times in seconds.
+pub fn table(rows: &[(&str, Metrics)]) -> String {
+    let header = [
+        "dispatch",
+        "delivered",
+        "wait mean",
+        "p50",
+        "p90",
+        "max",
+        "ride mean",
+        "p50",
+        "p90",
+        "max",
+        "floors",
+        "reversals",
+        "doors",
+    ];
+    let cells = |(name, metrics): &(&str, Metrics)| {
+        let summary = |summary: &Summary| {
+            [
+                format!("{:.1}", summary.mean),
+                summary.median.to_string(),
+                summary.p90.to_string(),
+                summary.max.to_string(),
+            ]
+        };
+        let mut cells = vec![
+            name.to_string(),
+            format!("{}/{}", metrics.ride.count, metrics.passengers),
+        ];
+        cells.extend(summary(&metrics.wait));
+        cells.extend(summary(&metrics.ride));
+        cells.extend([
+            metrics.floors_travelled.to_string(),
+            metrics.reversals.to_string(),
+            metrics.door_cycles.to_string(),
+        ]);
+        cells
+    };
+    let lines: Vec<Vec<String>> = std::iter::once(header.map(String::from).to_vec())
+        .chain(rows.iter().map(cells))
+        .collect();
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| lines.iter().map(|line| line[column].len()).max().unwrap())
+        .collect();
+
+    let mut text = String::new();
+    for line in &lines {
+        let mut row = format!("{:<1$}", line[0], widths[0]);
+        for (cell, width) in line.iter().zip(&widths).skip(1) {
+            row += &format!("  {cell:>width$}");
+        }
+        text += &row;
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::simulator::{Arrival, Trip};
+    use crate::elevator::{car_arrived, car_door_closed, car_door_opened, power_restored};
+
+    #[test]
+    fn test_summary() {
+        let summary = Summary::of((1..=10).rev());
+        assert_eq!(
+            summary,
+            Summary {
+                count: 10,
+                mean: 5.5,
+                median: 5,
+                p90: 9,
+                max: 10
+            }
+        );
+        assert_eq!(Summary::of([7]).p90, 7);
+        assert_eq!(Summary::of([]), Summary::default());
+    }
+
+    #[test]
+    fn test_collect() {
+        let trip = |time, boarded, alighted| Trip {
+            arrival: Arrival {
+                time,
+                origin: 0,
+                destination: 2,
+                weight: 70,
+            },
+            boarded,
+            alighted,
+            denied: 0,
+        };
+        let events = [
+            car_arrived(0, 1),
+            car_arrived(0, 2),
+            car_door_opened(0),
+            car_door_closed(0),
+            car_arrived(1, 4),
+            car_arrived(0, 1),
+            power_restored(0),
+            car_arrived(0, 1),
+            car_arrived(0, 0),
+            car_door_opened(0),
+            car_arrived(0, 1),
+        ];
+        let report = Report {
+            trips: vec![
+                trip(0, Some(4), Some(10)),
+                trip(2, Some(4), None),
+                trip(3, None, None),
+            ],
+            log: events.iter().map(|&event| (0, event)).collect(),
+            end: 10,
+        };
+        let metrics = collect(&report, &[0, 5]);
+        assert_eq!(metrics.passengers, 3);
+        assert_eq!((metrics.wait.count, metrics.wait.mean), (2, 3.0));
+        assert_eq!((metrics.ride.count, metrics.ride.max), (1, 6));
+        assert_eq!(metrics.floors_travelled, 6);
+        assert_eq!(metrics.reversals, 2);
+        assert_eq!(metrics.door_cycles, 2);
+    }
+
+    #[test]
+    fn test_table() {
+        let metrics = Metrics {
+            passengers: 20,
+            wait: Summary::of([4, 8, 12]),
+            ride: Summary::of([10, 20]),
+            floors_travelled: 140,
+            reversals: 9,
+            door_cycles: 31,
+        };
+        let other = Metrics {
+            floors_travelled: 96,
+            ..metrics.clone()
+        };
+        assert_eq!(
+            table(&[("look", metrics), ("round-robin", other)]),
+            "\
+dispatch     delivered  wait mean  p50  p90  max  ride mean  p50  p90  max  floors  reversals  doors
+look              2/20        8.0    8   12   12       15.0   10   20   20     140          9     31
+round-robin       2/20        8.0    8   12   12       15.0   10   20   20      96          9     31
+"
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Text drawings of a [`simulator`](super::simulator) run, to watch how the
+//! cars are dispatched.
+//!
+//! Each [`Frame`] shows the building at a moment something happened, a row
+//! per floor from the top down and a column per car, with the passengers
+//! waiting on each floor:
+//!
+//! ```text
+//! time 17
+//!     0   1
+//! 5   .   .
+//! 4   .  [ ]  v1
+//! 3   |   |
+//! 2  [=]  .   ^2  v1
+//! 1   .   .
+//! 0   .   .
+//! ```
+//!
+//! A car is drawn as `[=]` with its door closed and `[ ]` with it open, in a
+//! shaft showing a `.` at each floor it stops at and a `|` at each it only
+//! passes. `^` and `v` count the passengers waiting to go up and down.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use super::building::Building;
+use super::controller::Door;
+use super::simulator::{Report, Time};
+use super::{Direction, Event, Floor};
+
+/// The building drawn at one moment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub time: Time,
+    /// The drawing, a line per row, each ending in a newline.
+    pub text: String,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Draws the frames of a run, one for each moment an event happened, as
+/// things stood once every event of that moment was handled.
+#[derive(Debug, Clone)]
+pub struct Renderer<'a> {
+    building: &'a Building,
+    report: &'a Report,
+    /// Each car as of the events drawn so far.
+    cars: Vec<(Floor, Door)>,
+    /// The first event of the log not drawn yet.
+    next: usize,
+}
+
+impl<'a> Renderer<'a> {
+    /// The frames of `report`, a run in `building` whose cars started at
+    /// `floors` with their doors closed.
+    ///
+    /// # Panics
+    ///
+    /// If there is not a floor for every car of the building.
+    pub fn new(building: &'a Building, floors: &[Floor], report: &'a Report) -> Self {
+        assert_eq!(floors.len(), building.cars.len(), "a floor for every car");
+        Self {
+            building,
+            report,
+            cars: floors.iter().map(|&floor| (floor, Door::Closed)).collect(),
+            next: 0,
+        }
+    }
+
+    fn draw(&self, time: Time) -> String {
+        let label = |floor: Floor| floor.to_string().len();
+        let width = label(self.building.lowest).max(label(self.building.highest));
+        let mut text = format!("time {time}\n{:width$} ", "");
+        for car in 0..self.cars.len() {
+            text += &format!(" {car:^3}");
+        }
+        text = text.trim_end().to_string() + "\n";
+
+        for floor in (self.building.lowest..=self.building.highest).rev() {
+            let mut line = format!("{floor:>width$} ");
+            for (car, &(at, door)) in self.cars.iter().enumerate() {
+                line += match door {
+                    _ if at != floor && self.building.stops(car, floor) => "  . ",
+                    _ if at != floor => "  | ",
+                    Door::Closed => " [=]",
+                    Door::Open => " [ ]",
+                };
+            }
+            for (direction, arrow) in [(Direction::Up, '^'), (Direction::Down, 'v')] {
+                let waiting = self.waiting(time, floor, direction);
+                if waiting > 0 {
+                    line += &format!("  {arrow}{waiting}");
+                }
+            }
+            text += line.trim_end();
+            text.push('\n');
+        }
+        text
+    }
+
+    /// How many passengers are waiting at `floor` to go `direction` at
+    /// `time`.
+    fn waiting(&self, time: Time, floor: Floor, direction: Direction) -> usize {
+        (self.report.trips.iter())
+            .filter(|trip| trip.arrival.origin == floor && trip.arrival.direction() == direction)
+            .filter(|trip| trip.arrival.time <= time && trip.boarded.is_none_or(|t| t > time))
+            .count()
+    }
+}
+
+impl Iterator for Renderer<'_> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let &(time, _) = self.report.log.get(self.next)?;
+        while let Some(&(when, event)) = self.report.log.get(self.next)
+            && when == time
+        {
+            match event {
+                Event::CarArrived(car, floor) => self.cars[car].0 = floor,
+                Event::CarDoorOpened(car) => self.cars[car].1 = Door::Open,
+                Event::CarDoorClosed(car) => self.cars[car].1 = Door::Closed,
+                Event::PowerRestored(car) => self.cars[car].1 = Door::Closed,
+                Event::ButtonPressed(_)
+                | Event::PassengerBoarded(..)
+                | Event::PassengerAlighted(..)
+                | Event::ModeChanged(..) => {}
+            }
+            self.next += 1;
+        }
+        let text = self.draw(time);
+        Some(Frame { time, text })
+    }
+}
+
+/// Where to show frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    /// Draw each frame over the last on the terminal, and wait `delay`
+    /// before the next.
+    Stdout { delay: Duration },
+    /// Write all the frames to the file at this path.
+    File(PathBuf),
+}
+
+/// Show `frames` on `output`.
+pub fn show(frames: impl IntoIterator<Item = Frame>, output: &Output) -> io::Result<()> {
+    match output {
+        Output::Stdout { delay } => {
+            let mut stdout = io::stdout().lock();
+            for frame in frames {
+                // Clear the screen and go back to its top left corner.
+                write!(stdout, "\x1b[2J\x1b[H{frame}")?;
+                stdout.flush()?;
+                thread::sleep(*delay);
+            }
+            Ok(())
+        }
+        Output::File(path) => write(&mut BufWriter::new(File::create(path)?), frames),
+    }
+}
+
+/// Write `frames` to `out` one after another, a blank line apart.
+pub fn write(out: &mut impl Write, frames: impl IntoIterator<Item = Frame>) -> io::Result<()> {
+    for (i, frame) in frames.into_iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        write!(out, "{frame}")?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::controller::ElevatorController;
+    use crate::elevator::dispatch::NearestCar;
+    use crate::elevator::simulator::{Arrival, Simulator, Timing};
+
+    fn run() -> (Building, Report) {
+        let mut building = Building::new(-1, 2, 2);
+        building.skipped.insert(1);
+        let arrivals = [
+            Arrival {
+                time: 0,
+                origin: 0,
+                destination: 2,
+                weight: 70,
+            },
+            Arrival {
+                time: 0,
+                origin: 2,
+                destination: -1,
+                weight: 70,
+            },
+            Arrival {
+                time: 1,
+                origin: 2,
+                destination: 0,
+                weight: 70,
+            },
+        ];
+        let controller = ElevatorController::new(building.clone(), &[0, -1], NearestCar);
+        let report = Simulator::new(Timing::default(), controller, &arrivals).run();
+        (building, report)
+    }
+
+    #[test]
+    fn test_frames() {
+        let (building, report) = run();
+        let frames: Vec<_> = Renderer::new(&building, &[0, -1], &report).collect();
+        assert!(frames.is_sorted_by_key(|frame| frame.time));
+        assert_eq!(frames.last().unwrap().time, report.end);
+        assert_eq!(
+            frames[0].text,
+            "\
+time 0
+     0   1
+ 2   .   .   v1
+ 1   |   |
+ 0  [=]  .   ^1
+-1   .  [=]
+"
+        );
+        // The door opens for the first passenger as a third turns up.
+        assert_eq!(
+            frames[1].text,
+            "\
+time 1
+     0   1
+ 2   .   .   v2
+ 1   |   |
+ 0  [ ]  .
+-1   .  [=]
+"
+        );
+        // Car 0 passes the skipped floor on its way up.
+        assert_eq!(
+            frames[3].text,
+            "\
+time 7
+     0   1
+ 2   .   .   v2
+ 1  [=]  |
+ 0   .   .
+-1   .  [=]
+"
+        );
+    }
+
+    #[test]
+    fn test_file() {
+        let (building, report) = run();
+        let frames = || Renderer::new(&building, &[0, -1], &report);
+        let mut expected = vec![];
+        write(&mut expected, frames()).unwrap();
+        let text = String::from_utf8(expected).unwrap();
+        assert_eq!(text.matches("time ").count(), frames().count());
+        assert!(text.contains("\n\ntime 1\n"));
+
+        let path = std::env::temp_dir().join(format!("frames-{}.txt", std::process::id()));
+        show(frames(), &Output::File(path.clone())).unwrap();
+        let written = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written.unwrap(), text);
+    }
+}
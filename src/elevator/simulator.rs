@@ -0,0 +1,621 @@
+//! A discrete-event simulation of passengers using a bank of elevator cars run
+//! by an [`ElevatorController`].
+//!
+//! Time is virtual: the clock jumps from one scheduled happening to the next,
+//! so a day of traffic takes no longer to simulate than its events take to
+//! handle. A car carries out each [`Command`] by scheduling the [`Event`]
+//! that completes it, as far in the future as the [`Timing`] says it takes.
+//!
+//! Passengers get on the first car that opens its door on their floor going
+//! their way and stops at their destination, whichever car their call was
+//! assigned to. Those who would take the car over its capacity are turned
+//! away, and call again once its door has closed, unless they would not fit
+//! even in an empty car.
+//!
+//! Mode changes and power cuts ending can be [injected](Simulator::inject)
+//! at set times. A car whose power comes back forgets what it was doing and
+//! creeps to the nearest floor it stops at. Whenever a car has dropped calls
+//! this way, or by being taken out of service, its riders press their floors
+//! again and those waiting call again, as people do when nothing seems to
+//! happen. Riders stay on a recalled car until it is back in service.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::building::Building;
+use super::controller::{Command, ElevatorController};
+use super::{Button, CarId, Direction, Event, Floor, Mode, Weight};
+use crate::random::Rng;
+
+/// A point in simulated time, in seconds from the start.
+pub type Time = u64;
+
+/// How long a car takes to do things.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    /// To travel from one floor to the next.
+    pub travel: Time,
+    /// To open or to close the door.
+    pub door: Time,
+    /// To hold the door open for passengers.
+    pub dwell: Time,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            travel: 2,
+            door: 1,
+            dwell: 3,
+        }
+    }
+}
+
+/// A passenger turning up to be taken from one floor to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arrival {
+    pub time: Time,
+    pub origin: Floor,
+    pub destination: Floor,
+    pub weight: Weight,
+}
+
+impl Arrival {
+    pub fn direction(&self) -> Direction {
+        if self.destination > self.origin {
+            Direction::Up
+        } else {
+            Direction::Down
+        }
+    }
+}
+
+/// Where passengers go, relative to the lobby: floor 0 if the building has
+/// cars stopping there, and the lowest floor that has otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Most passengers go up from the lobby, as at the start of a day.
+    UpPeak,
+    /// Most passengers go down to the lobby, as at the end of a day.
+    DownPeak,
+    /// Passengers go between any two floors.
+    InterFloor,
+}
+
+/// The percentage of passengers going from or to the lobby at a peak; the
+/// others go between any two floors.
+pub const PEAK_SHARE: u32 = 80;
+
+/// Passengers weighing 50 to 100 kg travelling between the floors of a
+/// building.
+#[derive(Debug, Clone)]
+pub struct Traffic {
+    pub passengers: usize,
+    /// The average time between two passengers turning up.
+    pub mean_interval: Time,
+    pub profile: Profile,
+}
+
+impl Traffic {
+    /// The passengers, in the order they turn up, drawn from `seed`.
+    ///
+    /// Passengers only travel between floors that any car taking them on
+    /// also stops at, so that whichever car comes, it can get them there.
+    /// Returns `None` if there are no two such floors in `building`.
+    pub fn arrivals(&self, building: &Building, seed: u64) -> Option<Vec<Arrival>> {
+        let cars = 0..building.cars.len();
+        let served = |floor| cars.clone().filter(move |&car| building.stops(car, floor));
+        let trips: Vec<(Floor, Vec<Floor>)> = (building.lowest..=building.highest)
+            .map(|origin| {
+                let destinations: Vec<_> = (building.lowest..=building.highest)
+                    .filter(|&destination| destination != origin)
+                    .filter(|&destination| {
+                        served(origin).all(|car| building.stops(car, destination))
+                    })
+                    .collect();
+                (origin, destinations)
+            })
+            .filter(|(origin, destinations)| {
+                served(*origin).next().is_some() && !destinations.is_empty()
+            })
+            .collect();
+        if trips.is_empty() {
+            return None;
+        }
+
+        let lobby = (trips.iter())
+            .find(|(origin, _)| *origin == 0)
+            .unwrap_or(&trips[0]);
+        let to_lobby: Vec<Floor> = (trips.iter())
+            .filter(|(_, destinations)| destinations.contains(&lobby.0))
+            .map(|(origin, _)| *origin)
+            .collect();
+
+        let mut rng = Rng::new(seed);
+        let mut time = 0;
+        let pick = |rng: &mut Rng, len: usize| rng.below(len as u64) as usize;
+        let arrivals = (0..self.passengers)
+            .map(|_| {
+                time += rng.below(2 * self.mean_interval + 1);
+                let (origin, destination) = match self.profile {
+                    Profile::UpPeak if rng.chance(PEAK_SHARE) => {
+                        let (origin, destinations) = lobby;
+                        (*origin, destinations[pick(&mut rng, destinations.len())])
+                    }
+                    Profile::DownPeak if !to_lobby.is_empty() && rng.chance(PEAK_SHARE) => {
+                        (to_lobby[pick(&mut rng, to_lobby.len())], lobby.0)
+                    }
+                    _ => {
+                        let (origin, destinations) = &trips[pick(&mut rng, trips.len())];
+                        (*origin, destinations[pick(&mut rng, destinations.len())])
+                    }
+                };
+                Arrival {
+                    time,
+                    origin,
+                    destination,
+                    weight: rng.in_range(50..=100) as Weight,
+                }
+            })
+            .collect();
+        Some(arrivals)
+    }
+}
+
+/// What became of one passenger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trip {
+    pub arrival: Arrival,
+    pub boarded: Option<Time>,
+    pub alighted: Option<Time>,
+    /// How many times the passenger was turned away for lack of room.
+    pub denied: usize,
+}
+
+impl Trip {
+    /// How long the passenger waited for the car.
+    pub fn wait(&self) -> Option<Time> {
+        Some(self.boarded? - self.arrival.time)
+    }
+
+    /// How long the passenger spent in the car.
+    pub fn ride(&self) -> Option<Time> {
+        Some(self.alighted? - self.boarded?)
+    }
+}
+
+/// The outcome of a simulation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// One trip per passenger, in the order they turned up.
+    pub trips: Vec<Trip>,
+    /// Every event the controller handled, and when.
+    pub log: Vec<(Time, Event)>,
+    /// When the last event happened.
+    pub end: Time,
+}
+
+impl Report {
+    /// How many times passengers were turned away for lack of room.
+    pub fn denied_boardings(&self) -> usize {
+        self.trips.iter().map(|trip| trip.denied).sum()
+    }
+}
+
+/// Something scheduled to happen.
+#[derive(Debug, Clone, Copy)]
+enum Happening {
+    /// The passenger with this index turns up.
+    Arrival(usize),
+    /// A car completes a command.
+    Car(Event),
+    /// Something outside the cars' control.
+    Injected(Event),
+}
+
+/// Runs passengers through cars and their controller in simulated time.
+pub struct Simulator {
+    timing: Timing,
+    clock: Time,
+    /// Keyed by time, then by the order they were scheduled in, so
+    /// happenings at the same time happen first come, first served.
+    queue: BTreeMap<(Time, usize), Happening>,
+    scheduled: usize,
+    controller: ElevatorController,
+    trips: Vec<Trip>,
+    /// Indices of the passengers waiting for a car, and of those in each.
+    waiting: Vec<usize>,
+    riding: Vec<Vec<usize>>,
+    /// Indices of the passengers each car turned away while its door was
+    /// open.
+    denied: Vec<Vec<usize>>,
+    log: Vec<(Time, Event)>,
+}
+
+impl Simulator {
+    /// A simulation of `arrivals` for the cars of `controller`, which is
+    /// expected not to have handled any events yet.
+    pub fn new(timing: Timing, controller: ElevatorController, arrivals: &[Arrival]) -> Self {
+        let mut simulator = Self {
+            timing,
+            clock: 0,
+            queue: BTreeMap::new(),
+            scheduled: 0,
+            riding: vec![vec![]; controller.cars().len()],
+            denied: vec![vec![]; controller.cars().len()],
+            controller,
+            trips: vec![],
+            waiting: vec![],
+            log: vec![],
+        };
+        for (i, &arrival) in arrivals.iter().enumerate() {
+            simulator.schedule(arrival.time, Happening::Arrival(i));
+            simulator.trips.push(Trip {
+                arrival,
+                boarded: None,
+                alighted: None,
+                denied: 0,
+            });
+        }
+        simulator
+    }
+
+    /// Have `event`, such as a [mode change](Event::ModeChanged) or a car's
+    /// [power coming back](Event::PowerRestored), happen at `time`.
+    ///
+    /// # Panics
+    ///
+    /// When the run reaches the event, if the controller rejects it.
+    pub fn inject(&mut self, time: Time, event: Event) {
+        self.schedule(time, Happening::Injected(event));
+    }
+
+    /// Run until nothing is left to happen.
+    pub fn run(mut self) -> Report {
+        while let Some(((time, _), happening)) = self.queue.pop_first() {
+            self.clock = time;
+            match happening {
+                Happening::Arrival(passenger) => self.arrive(passenger),
+                Happening::Car(event) => {
+                    self.handle(event);
+                    match event {
+                        Event::CarDoorOpened(car) => self.exchange(car),
+                        Event::CarDoorClosed(car) => self.call_again(car),
+                        _ => {}
+                    }
+                }
+                Happening::Injected(event) => {
+                    if let Event::PowerRestored(car) = event {
+                        // Whatever the car was doing, it stopped doing.
+                        self.queue.retain(|_, happening| {
+                            !matches!(happening, Happening::Car(e) if e.car() == Some(car))
+                        });
+                    }
+                    self.handle(event);
+                    self.press_again();
+                }
+            }
+        }
+        Report {
+            trips: self.trips,
+            log: self.log,
+            end: self.clock,
+        }
+    }
+
+    fn schedule(&mut self, time: Time, happening: Happening) {
+        self.queue.insert((time, self.scheduled), happening);
+        self.scheduled += 1;
+    }
+
+    /// Pass `event` to the controller, and have the car it gives a command
+    /// carry it out.
+    fn handle(&mut self, event: Event) {
+        self.log.push((self.clock, event));
+        let command = (self.controller.handle(event))
+            .expect("the simulated car only reports what it was told to do");
+        let Some((car, command)) = command else {
+            return;
+        };
+        let (delay, event) = match command {
+            Command::Move(direction) => (
+                self.timing.travel,
+                Event::CarArrived(car, direction.step(self.controller.car(car).floor())),
+            ),
+            Command::OpenDoor => (self.timing.door, Event::CarDoorOpened(car)),
+            Command::CloseDoor => (
+                self.timing.dwell + self.timing.door,
+                Event::CarDoorClosed(car),
+            ),
+            Command::FindFloor => {
+                let floor = self.controller.car(car).floor();
+                let nearest = (self.controller.building().stops_of(car))
+                    .min_by_key(|stop| stop.abs_diff(floor))
+                    .unwrap_or(floor);
+                (self.timing.travel, Event::CarArrived(car, nearest))
+            }
+        };
+        self.schedule(self.clock + delay, Happening::Car(event));
+    }
+
+    /// The car with its door open where `passenger` waits, going their way
+    /// and stopping where they are going.
+    fn boarding_car(&self, passenger: usize) -> Option<CarId> {
+        let arrival = &self.trips[passenger].arrival;
+        (self.controller.cars().iter()).position(|car| {
+            car.is_boarding(arrival.direction(), arrival.origin) && car.serves(arrival.destination)
+        })
+    }
+
+    fn arrive(&mut self, passenger: usize) {
+        if let Some(car) = self.boarding_car(passenger) {
+            self.board(car, passenger);
+        } else {
+            self.waiting.push(passenger);
+            self.call(passenger);
+        }
+    }
+
+    fn call(&mut self, passenger: usize) {
+        let arrival = self.trips[passenger].arrival;
+        let call = Button::LobbyCall(arrival.direction(), arrival.origin);
+        self.handle(Event::ButtonPressed(call));
+    }
+
+    /// Let `passenger` on `car` if they fit, or else turn them away.
+    fn board(&mut self, car: CarId, passenger: usize) {
+        let Arrival {
+            destination,
+            weight,
+            ..
+        } = self.trips[passenger].arrival;
+        let controller = self.controller.car(car);
+        if !controller.fits(weight) {
+            self.trips[passenger].denied += 1;
+            // Someone too heavy for an empty car gives up.
+            if controller
+                .capacity()
+                .is_none_or(|capacity| weight <= capacity)
+            {
+                self.waiting.push(passenger);
+                self.denied[car].push(passenger);
+            }
+            return;
+        }
+        self.trips[passenger].boarded = Some(self.clock);
+        self.riding[car].push(passenger);
+        self.handle(Event::PassengerBoarded(car, weight));
+        self.handle(Event::ButtonPressed(Button::CarFloor(car, destination)));
+    }
+
+    /// Let passengers off and on through the door of `car` that just opened.
+    fn exchange(&mut self, car: CarId) {
+        let floor = self.controller.car(car).floor();
+        let (alighting, riding): (Vec<_>, _) = (self.riding[car].iter())
+            .partition(|&&passenger| self.trips[passenger].arrival.destination == floor);
+        self.riding[car] = riding;
+        for passenger in alighting {
+            self.trips[passenger].alighted = Some(self.clock);
+            let weight = self.trips[passenger].arrival.weight;
+            self.handle(Event::PassengerAlighted(car, weight));
+        }
+        let (boarding, waiting) = (self.waiting.iter().copied())
+            .partition(|&passenger| self.boarding_car(passenger) == Some(car));
+        self.waiting = waiting;
+        for passenger in boarding {
+            self.board(car, passenger);
+        }
+    }
+
+    /// Have riders press their floors again where their car forgot them,
+    /// unless it is recalled, and those waiting call again where no car
+    /// is coming.
+    fn press_again(&mut self) {
+        for car in 0..self.riding.len() {
+            let controller = self.controller.car(car);
+            if let Mode::FireRecall(_) = controller.mode() {
+                continue;
+            }
+            let called: BTreeSet<Floor> = controller.car_calls().collect();
+            let destinations: BTreeSet<Floor> = (self.riding[car].iter())
+                .map(|&passenger| self.trips[passenger].arrival.destination)
+                .filter(|destination| !called.contains(destination))
+                .collect();
+            for destination in destinations {
+                self.handle(Event::ButtonPressed(Button::CarFloor(car, destination)));
+            }
+        }
+        let calls: BTreeSet<(Direction, Floor)> = (self.waiting.iter())
+            .map(|&passenger| self.trips[passenger].arrival)
+            .map(|arrival| (arrival.direction(), arrival.origin))
+            .filter(|&(direction, floor)| self.controller.assignment(direction, floor).is_none())
+            .collect();
+        for (direction, floor) in calls {
+            self.handle(Event::ButtonPressed(Button::LobbyCall(direction, floor)));
+        }
+    }
+
+    /// Have the passengers `car` turned away and still waiting call again,
+    /// now that its door has closed.
+    fn call_again(&mut self, car: CarId) {
+        for passenger in std::mem::take(&mut self.denied[car]) {
+            if self.waiting.contains(&passenger) {
+                self.call(passenger);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::dispatch::{Dispatch, Look, NearestCar, RoundRobin};
+    use crate::elevator::invariants;
+
+    fn one_car() -> ElevatorController {
+        ElevatorController::new(Building::new(0, 9, 1), &[0], NearestCar)
+    }
+
+    #[test]
+    fn test_single_passenger() {
+        let arrival = Arrival {
+            time: 5,
+            origin: 0,
+            destination: 3,
+            weight: 70,
+        };
+        let timing = Timing {
+            travel: 2,
+            door: 1,
+            dwell: 3,
+        };
+        let report = Simulator::new(timing, one_car(), &[arrival]).run();
+        // The door opens a second after the call, stays open for three
+        // seconds and closes in one, then the car climbs three floors.
+        assert_eq!(report.trips[0].wait(), Some(1));
+        assert_eq!(report.trips[0].ride(), Some(3 + 1 + 3 * 2 + 1));
+        assert_eq!(
+            report.log[0],
+            (5, Event::ButtonPressed(Button::LobbyCall(Direction::Up, 0)))
+        );
+        assert_eq!(report.log[1], (6, Event::CarDoorOpened(0)));
+        assert_eq!(report.log[2], (6, Event::PassengerBoarded(0, 70)));
+        assert_eq!(
+            report.log[3],
+            (6, Event::ButtonPressed(Button::CarFloor(0, 3)))
+        );
+        assert_eq!(report.end, 6 + 11 + 3 + 1);
+
+        // Someone turning up while the door is open gets straight on.
+        let late = Arrival { time: 8, ..arrival };
+        let report = Simulator::new(timing, one_car(), &[arrival, late]).run();
+        assert_eq!(report.trips[1].wait(), Some(0));
+        assert_eq!(report.trips[1].alighted, report.trips[0].alighted);
+    }
+
+    #[test]
+    fn test_everyone_arrives() {
+        let traffic = Traffic {
+            passengers: 200,
+            mean_interval: 10,
+            profile: Profile::InterFloor,
+        };
+        let building = |cars| Building::new(-2, 9, cars);
+        let arrivals = traffic.arrivals(&building(1), 42).unwrap();
+        assert_eq!(Some(&arrivals), traffic.arrivals(&building(3), 42).as_ref());
+        assert_ne!(Some(&arrivals), traffic.arrivals(&building(1), 43).as_ref());
+        assert_eq!(traffic.arrivals(&Building::new(0, 0, 1), 42), None);
+        assert!(arrivals.is_sorted_by_key(|arrival| arrival.time));
+
+        let timing = Timing::default();
+        let run = |floors: &[Floor], dispatch: fn() -> Box<dyn Dispatch>| {
+            let new = || ElevatorController::new(building(floors.len()), floors, dispatch());
+            let report = Simulator::new(timing, new(), &arrivals).run();
+            let controller = new();
+            assert_eq!(report, Simulator::new(timing, controller, &arrivals).run());
+            assert_eq!(report.trips.len(), 200);
+            for trip in &report.trips {
+                let floors = trip.arrival.origin.abs_diff(trip.arrival.destination);
+                assert!(trip.ride().unwrap() >= u64::from(floors) * timing.travel);
+                assert!(trip.wait().is_some());
+            }
+            let waits = report.trips.iter().map(|trip| trip.wait().unwrap());
+            waits.sum::<Time>() / 200
+        };
+        let one = run(&[0], || Box::new(NearestCar));
+        let three = [
+            run(&[0, 0, 0], || Box::new(NearestCar)),
+            run(&[0, 0, 0], || Box::new(Look)),
+            run(&[0, 0, 0], || Box::new(RoundRobin::default())),
+        ];
+        assert!(three.iter().all(|&wait| wait < one), "{one} {three:?}");
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut building = Building::new(0, 5, 1);
+        building.capacity = Some(150);
+        let passenger = |time, origin, destination, weight| Arrival {
+            time,
+            origin,
+            destination,
+            weight,
+        };
+        let arrivals = [
+            passenger(0, 0, 5, 100),
+            passenger(0, 0, 5, 100),
+            passenger(0, 0, 4, 40),
+            passenger(1, 0, 5, 200),
+        ];
+        let controller = ElevatorController::new(building.clone(), &[0], NearestCar);
+        let report = Simulator::new(Timing::default(), controller, &arrivals).run();
+        let denied: Vec<_> = report.trips.iter().map(|trip| trip.denied).collect();
+        // The second passenger waits for the car to come back, the third
+        // squeezes in, and the last is too heavy for the car at all.
+        assert_eq!(denied, [0, 1, 0, 1]);
+        assert_eq!(report.denied_boardings(), 2);
+        assert!(report.trips[1].boarded > report.trips[0].alighted);
+        assert_eq!(report.trips[2].boarded, report.trips[0].boarded);
+        assert_eq!(report.trips[3].boarded, None);
+        let events = report.log.iter().map(|&(_, event)| event);
+        assert_eq!(invariants::check(events, &building, &[0]), []);
+
+        // Loaded past the bypass, the car passes a call on the way up.
+        building.capacity = Some(120);
+        let arrivals = [passenger(0, 0, 5, 100), passenger(2, 2, 4, 60)];
+        let controller = ElevatorController::new(building, &[0], NearestCar);
+        let report = Simulator::new(Timing::default(), controller, &arrivals).run();
+        assert_eq!(report.denied_boardings(), 0);
+        assert!(report.trips[1].boarded > report.trips[0].alighted);
+    }
+
+    #[test]
+    fn test_zoned_building() {
+        let building = Building::parse(
+            "floors -1 20\ncars 3\nskip 13\nexpress 1..=5\ncar 1 -1 0 14..=20\ncar 2 0 14..=20",
+        )
+        .unwrap();
+        let traffic = Traffic {
+            passengers: 150,
+            mean_interval: 5,
+            profile: Profile::InterFloor,
+        };
+        let arrivals = traffic.arrivals(&building, 3).unwrap();
+        for arrival in &arrivals {
+            for car in 0..3 {
+                if building.stops(car, arrival.origin) {
+                    assert!(building.stops(car, arrival.destination), "{arrival:?}");
+                }
+            }
+        }
+
+        let controller = ElevatorController::new(building, &[0, 0, 20], Look);
+        let report = Simulator::new(Timing::default(), controller, &arrivals).run();
+        assert!(report.trips.iter().all(|trip| trip.alighted.is_some()));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let arrivals = |profile, building: &Building| {
+            let traffic = Traffic {
+                passengers: 500,
+                mean_interval: 5,
+                profile,
+            };
+            traffic.arrivals(building, 11).unwrap()
+        };
+        let share = |arrivals: &[Arrival], lobby: fn(&Arrival) -> bool| {
+            arrivals.iter().filter(|&arrival| lobby(arrival)).count() * 100 / arrivals.len()
+        };
+        let building = Building::new(-2, 9, 2);
+        let up = arrivals(Profile::UpPeak, &building);
+        assert!(share(&up, |arrival| arrival.origin == 0) >= 75);
+        let down = arrivals(Profile::DownPeak, &building);
+        assert!(share(&down, |arrival| arrival.destination == 0) >= 75);
+        let inter = arrivals(Profile::InterFloor, &building);
+        assert!(share(&inter, |arrival| arrival.origin == 0) <= 20);
+
+        // Without a floor 0, the lobby is the lowest floor.
+        let up = arrivals(Profile::UpPeak, &Building::new(1, 10, 1));
+        assert!(share(&up, |arrival| arrival.origin == 1) >= 75);
+    }
+}
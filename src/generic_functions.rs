@@ -13,19 +13,28 @@ fn duplicate<T: Clone>(a: T) -> (T, T) {
 struct NotClonable;
 
 pub trait Logger {
+    /// Log a message at the given verbosity level.
     fn log(&self, verbosity: u8, message: &str);
 }
 
-struct StderrLogger;
+/// Lets a logger be shared, e.g. wrapped in a filter while still in use.
+impl<L: Logger + ?Sized> Logger for &L {
+    fn log(&self, verbosity: u8, message: &str) {
+        (**self).log(verbosity, message);
+    }
+}
+
+pub struct StderrLogger;
 impl Logger for StderrLogger {
     fn log(&self, verbosity: u8, message: &str) {
         eprintln!("verbosity={verbosity}: {message}")
     }
 }
 
-struct VerbosityFilter<L> {
-    max_verbosity: u8,
-    inner: L,
+/// Only passes on messages up to `max_verbosity`.
+pub struct VerbosityFilter<L> {
+    pub max_verbosity: u8,
+    pub inner: L,
 }
 
 impl<L: Logger> Logger for VerbosityFilter<L> {
@@ -0,0 +1,8 @@
+pub mod closures;
+pub mod elevator;
+pub mod generic_functions;
+mod memory_management;
+mod method_and_traits;
+pub mod pattern_matching;
+pub mod random;
+pub mod smart_pointer;
@@ -1,15 +1,14 @@
-mod closures;
-mod generic_functions;
-mod memory_management;
-mod method_and_traits;
-mod pattern_matching;
-mod smart_pointer;
-
 use std::{
     cell::RefCell,
     sync::{Arc, RwLock},
 };
 
+use exercise::elevator::{
+    Direction, building::Building, car_arrived, car_door_closed, car_door_opened,
+    car_floor_button_pressed, controller::ElevatorController, dispatch::NearestCar,
+    lobby_call_button_pressed,
+};
+
 fn main() {
     let x = 10;
     let y = 20;
@@ -118,21 +117,28 @@ fn main() {
 
     println!("{BANNER}");
 
-    println!(
-        "A ground floor passenger has pressed the up button: {:?}",
-        lobby_call_button_pressed(0, Direction::Up)
-    );
-    println!(
-        "The car has arrived on the ground floor: {:?}",
-        car_arrived(0)
-    );
-    println!("The car door opened: {:?}", car_door_opened());
-    println!(
-        "A passenger has pressed the 3rd floor button: {:?}",
-        car_floor_button_pressed(3)
-    );
-    println!("The car door closed: {:?}", car_door_closed());
-    println!("The car has arrived on the 3rd floor: {:?}", car_arrived(3));
+    let mut controller = ElevatorController::new(Building::new(0, 9, 1), &[0], NearestCar);
+    for (what, event) in [
+        (
+            "A ground floor passenger has pressed the up button",
+            lobby_call_button_pressed(0, Direction::Up),
+        ),
+        ("The car door opened", car_door_opened(0)),
+        (
+            "A passenger has pressed the 3rd floor button",
+            car_floor_button_pressed(0, 3),
+        ),
+        ("The car door closed", car_door_closed(0)),
+        ("The car has arrived on the 1st floor", car_arrived(0, 1)),
+        ("The car has arrived on the 2nd floor", car_arrived(0, 2)),
+        ("The car has arrived on the 3rd floor", car_arrived(0, 3)),
+        ("The car has arrived on the 4th floor", car_arrived(0, 4)),
+    ] {
+        match controller.handle(event) {
+            Ok(command) => println!("{what}: {event:?} -> {command:?}"),
+            Err(e) => println!("{what}: {event:?} rejected, {e}"),
+        }
+    }
 }
 
 /// https://google.github.io/comprehensive-rust/types-and-values/arithmetic.html
@@ -440,63 +446,3 @@ fn compute_digest(text: &str) -> [u8; DIGEST_SIZE] {
 }
 
 static BANNER: &str = "Welcome to RustOS 3.14";
-
-#[derive(Debug)]
-/// An event in the elevator system that the controller must react to.
-enum Event {
-    /// A button was pressed.
-    ButtonPressed(Button),
-
-    /// The car has arrived at the given floor.
-    CarArrived(Floor),
-
-    /// The car's doors have opened.
-    CarDoorOpened,
-
-    /// The car's doors have closed.
-    CarDoorClosed,
-}
-
-/// A floor is represented as an integer.
-type Floor = i32;
-
-#[derive(Debug)]
-/// A direction of travel.
-enum Direction {
-    Up,
-    Down,
-}
-
-#[derive(Debug)]
-enum Button {
-    /// A button in the elevator lobby on the given floor.
-    LobbyCall(Direction, Floor),
-
-    /// A floor button within the car.
-    CarFloor(Floor),
-}
-
-/// The car has arrived on the given floor.
-fn car_arrived(floor: i32) -> Event {
-    Event::CarArrived(floor)
-}
-
-/// The car doors have opened.
-fn car_door_opened() -> Event {
-    Event::CarDoorOpened
-}
-
-/// The car doors have closed.
-fn car_door_closed() -> Event {
-    Event::CarDoorClosed
-}
-
-/// A directional button was pressed in an elevator lobby on the given floor.
-fn lobby_call_button_pressed(floor: i32, dir: Direction) -> Event {
-    Event::ButtonPressed(Button::LobbyCall(dir, floor))
-}
-
-/// A floor buton was pressed in the elevator car.
-fn car_floor_button_pressed(floor: i32) -> Event {
-    Event::ButtonPressed(Button::CarFloor(floor))
-}
@@ -1,4 +1,17 @@
-use std::time::Duration;
+use std::{cmp::Ordering, collections::HashMap, fmt, time::Duration};
+
+pub mod dag;
+pub mod derive;
+pub mod display;
+pub mod generate;
+pub mod number;
+pub mod parser;
+pub mod simplify;
+pub mod trace;
+pub mod typecheck;
+pub mod vm;
+
+use number::{Arithmetic, ArithmeticError, Checked};
 
 fn take_tuple(tuple: (char, i32, bool)) {
     let a = tuple.0;
@@ -48,17 +61,81 @@ fn sleep_for(secs: f32) {
 }
 
 /// An operation to perform on two subexpressions.
-#[derive(Debug)]
-enum Operation {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
     Add,
     Sub,
     Mul,
     Div,
+    /// The remainder of a division, with the sign of the left operand.
+    Mod,
+    /// The left operand raised to the power of the right one.
+    Pow,
+    /// A comparison, giving a boolean.
+    Compare(Comparison),
+}
+
+/// The ways [`Operation::Compare`] can compare its operands. Only `Eq` and
+/// `Ne` apply to booleans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Comparison {
+    /// Whether the comparison holds for operands that order as `ordering`,
+    /// where `None` means they are unordered, like a NaN and anything.
+    fn holds(self, ordering: Option<Ordering>) -> bool {
+        match ordering {
+            Some(ordering) => match self {
+                Comparison::Lt => ordering.is_lt(),
+                Comparison::Le => ordering.is_le(),
+                Comparison::Gt => ordering.is_gt(),
+                Comparison::Ge => ordering.is_ge(),
+                Comparison::Eq => ordering.is_eq(),
+                Comparison::Ne => ordering.is_ne(),
+            },
+            None => self == Comparison::Ne,
+        }
+    }
+}
+
+/// How tightly unary negation binds: tighter than `*`, looser than `^`, so
+/// `-2 ^ 2` is `-(2 ^ 2)`.
+const NEG_PRECEDENCE: u8 = 4;
+
+impl Operation {
+    /// How tightly this operation binds its operands; higher binds tighter.
+    ///
+    /// Zero is left for `let`, `if` and `fn`, which extend as far right as
+    /// they can.
+    fn precedence(self) -> u8 {
+        match self {
+            Operation::Compare(_) => 1,
+            Operation::Add | Operation::Sub => 2,
+            Operation::Mul | Operation::Div | Operation::Mod => 3,
+            Operation::Pow => 5,
+        }
+    }
+
+    /// Whether `a op b op c` groups as `a op (b op c)`.
+    fn is_right_associative(self) -> bool {
+        self == Operation::Pow
+    }
 }
 
 /// An expression, in tree form.
-#[derive(Debug)]
-enum Expression {
+///
+/// [`parse`](parser::parse) folds chains like `1 + 1 + ... + 1` into trees as
+/// deep as the chain is long, so cloning and dropping walk down the left
+/// operands of operations in a loop rather than recursing.
+#[derive(Debug, PartialEq)]
+pub enum Expression {
     /// An operation on two subexpressions.
     Op {
         op: Operation,
@@ -66,23 +143,575 @@ enum Expression {
         right: Box<Expression>,
     },
 
+    /// The negation of a subexpression.
+    Neg(Box<Expression>),
+
     /// A literal value
     Value(i64),
+
+    /// A literal boolean, `true` or `false`.
+    Bool(bool),
+
+    /// A named variable, bound by an enclosing `let` or function parameter,
+    /// or else looked up in an [`Environment`] at evaluation time.
+    Var(String),
+
+    /// `let name = value in body`: `body` with `name` bound to `value`.
+    Let {
+        name: String,
+        value: Box<Expression>,
+        body: Box<Expression>,
+    },
+
+    /// `if condition then consequent else alternative`. The condition must be
+    /// a boolean, and only the chosen branch is evaluated.
+    If {
+        condition: Box<Expression>,
+        consequent: Box<Expression>,
+        alternative: Box<Expression>,
+    },
+
+    /// `fn name(params) = body in rest`: `rest` with a function it can call.
+    ///
+    /// The body sees the parameters, the function itself and whatever is in
+    /// scope where the function is defined.
+    Function {
+        name: String,
+        params: Vec<String>,
+        body: Box<Expression>,
+        rest: Box<Expression>,
+    },
+
+    /// `name(args)`: a call of the nearest enclosing function called `name`.
+    Call { name: String, args: Vec<Expression> },
+}
+
+impl Expression {
+    /// Move the expression out, leaving a literal in its place.
+    fn take(&mut self) -> Expression {
+        std::mem::replace(self, Expression::Value(0))
+    }
+
+    /// The operations down the left operands from `self`, outermost first,
+    /// as `(op, left, right)`, and the first left operand that is not an
+    /// operation.
+    fn left_spine(&self) -> (Vec<(Operation, &Expression, &Expression)>, &Expression) {
+        let mut spine = vec![];
+        let mut bottom = self;
+        while let Expression::Op { op, left, right } = bottom {
+            spine.push((*op, &**left, &**right));
+            bottom = left;
+        }
+        (spine, bottom)
+    }
+}
+
+impl Clone for Expression {
+    fn clone(&self) -> Self {
+        let (spine, bottom) = self.left_spine();
+        let mut clone = match bottom {
+            Expression::Op { .. } => unreachable!("the spine ends below the operations"),
+            Expression::Neg(operand) => Expression::Neg(operand.clone()),
+            Expression::Value(v) => Expression::Value(*v),
+            Expression::Bool(b) => Expression::Bool(*b),
+            Expression::Var(name) => Expression::Var(name.clone()),
+            Expression::Let { name, value, body } => Expression::Let {
+                name: name.clone(),
+                value: value.clone(),
+                body: body.clone(),
+            },
+            Expression::If {
+                condition,
+                consequent,
+                alternative,
+            } => Expression::If {
+                condition: condition.clone(),
+                consequent: consequent.clone(),
+                alternative: alternative.clone(),
+            },
+            Expression::Function {
+                name,
+                params,
+                body,
+                rest,
+            } => Expression::Function {
+                name: name.clone(),
+                params: params.clone(),
+                body: body.clone(),
+                rest: rest.clone(),
+            },
+            Expression::Call { name, args } => Expression::Call {
+                name: name.clone(),
+                args: args.clone(),
+            },
+        };
+        for (op, _, right) in spine.into_iter().rev() {
+            clone = Expression::Op {
+                op,
+                left: Box::new(clone),
+                right: Box::new(right.clone()),
+            };
+        }
+        clone
+    }
+}
+
+impl Drop for Expression {
+    fn drop(&mut self) {
+        let Expression::Op { left, .. } = self else {
+            return;
+        };
+        // Each operation dropped here has a literal for its left operand, so
+        // dropping it does not recurse any further.
+        let mut spine = left.take();
+        while let Expression::Op { left, .. } = &mut spine {
+            spine = left.take();
+        }
+    }
+}
+
+/// The kinds of value an [`Expression`] can evaluate to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Number,
+    Bool,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Number => write!(f, "a number"),
+            Type::Bool => write!(f, "a boolean"),
+        }
+    }
+}
+
+/// What an [`Expression`] evaluates to: a number of whatever kind the
+/// evaluator works with, or a boolean.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<N = i64> {
+    Number(N),
+    Bool(bool),
+}
+
+impl<N> Value<N> {
+    pub fn type_of(&self) -> Type {
+        match self {
+            Value::Number(_) => Type::Number,
+            Value::Bool(_) => Type::Bool,
+        }
+    }
+
+    fn number(self) -> std::result::Result<N, ArithmeticError> {
+        match self {
+            Value::Number(v) => Ok(v),
+            Value::Bool(_) => Err(ArithmeticError::TypeMismatch {
+                expected: Type::Number,
+                found: Type::Bool,
+            }),
+        }
+    }
+
+    fn boolean(self) -> std::result::Result<bool, ArithmeticError> {
+        match self {
+            Value::Bool(b) => Ok(b),
+            Value::Number(_) => Err(ArithmeticError::TypeMismatch {
+                expected: Type::Bool,
+                found: Type::Number,
+            }),
+        }
+    }
+
+    fn map<M>(self, f: impl FnOnce(N) -> M) -> Value<M> {
+        match self {
+            Value::Number(v) => Value::Number(f(v)),
+            Value::Bool(b) => Value::Bool(b),
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Number(v)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<Value> for Expression {
+    fn from(v: Value) -> Self {
+        match v {
+            Value::Number(v) => Expression::Value(v),
+            Value::Bool(b) => Expression::Bool(b),
+        }
+    }
+}
+
+impl Expression {
+    /// The value of a literal, or `None` for any other expression.
+    fn literal(&self) -> Option<Value> {
+        match self {
+            Expression::Value(v) => Some(Value::Number(*v)),
+            Expression::Bool(b) => Some(Value::Bool(*b)),
+            _ => None,
+        }
+    }
+}
+
+impl<N: fmt::Display> fmt::Display for Value<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(v) => write!(f, "{v}"),
+            Value::Bool(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+/// The variable bindings an [`Expression`] is evaluated against.
+///
+/// One parsed expression can be evaluated repeatedly against different
+/// environments. The environment also caps how deeply function calls may nest,
+/// so runaway recursion fails with [`EvalError::RecursionLimit`] instead of
+/// overflowing the stack.
+#[derive(Debug, Clone)]
+pub struct Environment {
+    values: HashMap<String, Value>,
+    max_depth: usize,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            values: HashMap::new(),
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+impl Environment {
+    /// How many function calls may be in progress at once, unless changed
+    /// with [`set_max_depth`](Self::set_max_depth). Low enough for the
+    /// recursive evaluators to stay within a small thread stack, even in an
+    /// unoptimized build; [`vm`] keeps its frames on the heap and can be
+    /// allowed much more.
+    pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Allow at most `max_depth` function calls to be in progress at once.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Bind `name` to `value`, replacing any previous binding.
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<Value>) {
+        self.values.insert(name.into(), value.into());
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.values.get(name).copied()
+    }
+}
+
+/// Why evaluating an [`Expression`] failed.
+///
+/// Each variant carries the sub-expression whose operation failed, so callers
+/// can point at the offending part of a larger tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// The right operand of a division or remainder evaluated to zero.
+    DivisionByZero(Expression),
+
+    /// The result of an operation does not fit in an `i64`.
+    Overflow(Expression),
+
+    /// An integer was raised to a negative power.
+    NegativeExponent(Expression),
+
+    /// A variable has no binding in the environment.
+    UnboundVariable(String),
+
+    /// No function of this name is in scope.
+    UnknownFunction(String),
+
+    /// A call passes a different number of arguments than the function has
+    /// parameters.
+    ArgumentCount { call: Expression, expected: usize },
+
+    /// This call would nest deeper than [`Environment::max_depth`] calls.
+    RecursionLimit(Expression),
+
+    /// An operand or condition has the wrong type, like the `true` in
+    /// `true + 1`. [`typecheck::check`] rules these out before evaluation.
+    TypeMismatch {
+        expression: Expression,
+        expected: Type,
+        found: Type,
+    },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::DivisionByZero(e) => write!(f, "division by zero in `{e}`"),
+            EvalError::Overflow(e) => write!(f, "integer overflow in `{e}`"),
+            EvalError::NegativeExponent(e) => {
+                write!(f, "negative exponent in integer power `{e}`")
+            }
+            EvalError::UnboundVariable(name) => write!(f, "unbound variable `{name}`"),
+            EvalError::UnknownFunction(name) => write!(f, "unknown function `{name}`"),
+            EvalError::ArgumentCount { call, expected } => {
+                write!(f, "`{call}` passes the wrong number of arguments, ")?;
+                write!(f, "expected {expected}")
+            }
+            EvalError::RecursionLimit(call) => {
+                write!(f, "recursion limit exceeded in `{call}`")
+            }
+            EvalError::TypeMismatch {
+                expression,
+                expected,
+                found,
+            } => write!(f, "expected {expected}, found {found} in `{expression}`"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// `Result` is shadowed by the exercise above, so spell out the std one.
+pub type EvalResult<T> = std::result::Result<T, EvalError>;
+
+/// Evaluate an expression without variables, reporting division by zero and
+/// overflow as errors instead of panicking.
+pub fn try_eval(e: &Expression) -> EvalResult<Value> {
+    try_eval_in(e, &Environment::new())
+}
+
+/// Evaluate an expression, looking its free variables up in `env`.
+pub fn try_eval_in(e: &Expression, env: &Environment) -> EvalResult<Value> {
+    evaluate(e, &Scope::Global(env), 0, &Checked)
+}
+
+/// The names visible at some point of an expression: the variables of an
+/// [`Environment`], extended by the `let`s, parameters and functions that
+/// enclose that point.
+enum Scope<'a, N> {
+    Global(&'a Environment),
+    Vars {
+        names: &'a [String],
+        values: Vec<Value<N>>,
+        parent: &'a Scope<'a, N>,
+    },
+    Function {
+        name: &'a str,
+        params: &'a [String],
+        body: &'a Expression,
+        parent: &'a Scope<'a, N>,
+    },
 }
 
-fn eval(e: Expression) -> i64 {
+impl<N: Clone> Scope<'_, N> {
+    fn environment(&self) -> &Environment {
+        match self {
+            Scope::Global(env) => env,
+            Scope::Vars { parent, .. } | Scope::Function { parent, .. } => parent.environment(),
+        }
+    }
+
+    /// The value of a variable, or `None` if it is not even in the environment.
+    fn var(&self, name: &str, arith: &impl Arithmetic<Number = N>) -> Option<Value<N>> {
+        match self {
+            Scope::Global(env) => env.get(name).map(|v| v.map(|v| arith.literal(v))),
+            Scope::Vars {
+                names,
+                values,
+                parent,
+            } => match names.iter().rposition(|n| n == name) {
+                Some(i) => Some(values[i].clone()),
+                None => parent.var(name, arith),
+            },
+            Scope::Function { parent, .. } => parent.var(name, arith),
+        }
+    }
+
+    /// The `Scope::Function` that `call`, a [`Expression::Call`], refers to,
+    /// checking that it takes as many arguments as the call passes.
+    fn function(&self, call: &Expression) -> EvalResult<&Self> {
+        let Expression::Call { name, args } = call else {
+            unreachable!("only calls refer to functions");
+        };
+        let mut scope = self;
+        loop {
+            scope = match scope {
+                Scope::Global(_) => return Err(EvalError::UnknownFunction(name.clone())),
+                Scope::Function {
+                    name: n, params, ..
+                } if n == name => {
+                    if params.len() != args.len() {
+                        return Err(EvalError::ArgumentCount {
+                            call: call.clone(),
+                            expected: params.len(),
+                        });
+                    }
+                    return Ok(scope);
+                }
+                Scope::Vars { parent, .. } | Scope::Function { parent, .. } => parent,
+            };
+        }
+    }
+}
+
+/// Evaluate an expression with the given arithmetic. `calls` is the number of
+/// function calls already in progress.
+fn evaluate<A: Arithmetic>(
+    e: &Expression,
+    scope: &Scope<A::Number>,
+    calls: usize,
+    arith: &A,
+) -> EvalResult<Value<A::Number>> {
     match e {
         Expression::Op { op, left, right } => {
-            let left = eval(*left);
-            let right = eval(*right);
-            match op {
-                Operation::Add => left + right,
-                Operation::Sub => left - right,
-                Operation::Mul => left * right,
-                Operation::Div => left / right,
+            let left = evaluate(left, scope, calls, arith)?;
+            let right = evaluate(right, scope, calls, arith)?;
+            operate(arith, *op, left, right).map_err(|err| err.at(e))
+        }
+        Expression::Neg(operand) => {
+            let operand = evaluate(operand, scope, calls, arith)?;
+            negate(arith, operand).map_err(|err| err.at(e))
+        }
+        Expression::Value(v) => Ok(Value::Number(arith.literal(*v))),
+        Expression::Bool(b) => Ok(Value::Bool(*b)),
+        Expression::Var(name) => scope
+            .var(name, arith)
+            .ok_or_else(|| EvalError::UnboundVariable(name.clone())),
+        Expression::Let { name, value, body } => {
+            let value = evaluate(value, scope, calls, arith)?;
+            let scope = Scope::Vars {
+                names: std::slice::from_ref(name),
+                values: vec![value],
+                parent: scope,
+            };
+            evaluate(body, &scope, calls, arith)
+        }
+        Expression::If {
+            condition,
+            consequent,
+            alternative,
+        } => {
+            let taken = evaluate(condition, scope, calls, arith)?.boolean();
+            if taken.map_err(|err| err.at(condition))? {
+                evaluate(consequent, scope, calls, arith)
+            } else {
+                evaluate(alternative, scope, calls, arith)
             }
         }
-        Expression::Value(v) => v,
+        Expression::Function {
+            name,
+            params,
+            body,
+            rest,
+        } => {
+            let scope = Scope::Function {
+                name,
+                params,
+                body,
+                parent: scope,
+            };
+            evaluate(rest, &scope, calls, arith)
+        }
+        Expression::Call { args, .. } => {
+            let function = scope.function(e)?;
+            let args = args
+                .iter()
+                .map(|arg| evaluate(arg, scope, calls, arith))
+                .collect::<EvalResult<_>>()?;
+            call(e, function, args, calls, arith)
+        }
+    }
+}
+
+/// Evaluate the body of `function`, a [`Scope::Function`], with its parameters
+/// bound to `args`. `e` is the call, reported back if it nests too deeply.
+fn call<A: Arithmetic>(
+    e: &Expression,
+    function: &Scope<A::Number>,
+    args: Vec<Value<A::Number>>,
+    calls: usize,
+    arith: &A,
+) -> EvalResult<Value<A::Number>> {
+    let Scope::Function { params, body, .. } = function else {
+        unreachable!("calls are resolved to functions");
+    };
+    if calls >= function.environment().max_depth() {
+        return Err(EvalError::RecursionLimit(e.clone()));
+    }
+    // The body sees the function itself, so it can recurse.
+    let scope = Scope::Vars {
+        names: params,
+        values: args,
+        parent: function,
+    };
+    evaluate(body, &scope, calls + 1, arith)
+}
+
+/// Apply `op` to two evaluated operands, checking that their types suit it:
+/// arithmetic and ordering take numbers, while `==` and `!=` take any two
+/// values of the same type.
+fn operate<A: Arithmetic>(
+    arith: &A,
+    op: Operation,
+    left: Value<A::Number>,
+    right: Value<A::Number>,
+) -> std::result::Result<Value<A::Number>, ArithmeticError> {
+    let Operation::Compare(c) = op else {
+        return Ok(Value::Number(arith.binary(
+            op,
+            left.number()?,
+            right.number()?,
+        )?));
+    };
+    match (left, right) {
+        (Value::Bool(l), Value::Bool(r)) if matches!(c, Comparison::Eq | Comparison::Ne) => {
+            Ok(Value::Bool(c.holds(Some(l.cmp(&r)))))
+        }
+        (Value::Bool(_), _) if !matches!(c, Comparison::Eq | Comparison::Ne) => {
+            Err(ArithmeticError::TypeMismatch {
+                expected: Type::Number,
+                found: Type::Bool,
+            })
+        }
+        (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(arith.compare(c, &l, &r))),
+        (left, right) => Err(ArithmeticError::TypeMismatch {
+            expected: left.type_of(),
+            found: right.type_of(),
+        }),
+    }
+}
+
+fn negate<A: Arithmetic>(
+    arith: &A,
+    v: Value<A::Number>,
+) -> std::result::Result<Value<A::Number>, ArithmeticError> {
+    Ok(Value::Number(arith.negate(v.number()?)?))
+}
+
+/// Evaluate an expression, panicking if that fails or gives a boolean. See
+/// [`try_eval`].
+pub fn eval(e: Expression) -> i64 {
+    match try_eval(&e) {
+        Ok(Value::Number(v)) => v,
+        Ok(Value::Bool(b)) => panic!("expected a number, found `{b}`"),
+        Err(err) => panic!("{err}"),
     }
 }
 
@@ -168,3 +797,242 @@ fn test_div() {
         5
     )
 }
+
+#[test]
+fn test_div_by_zero() {
+    let div = Expression::Op {
+        op: Operation::Div,
+        left: Box::new(Expression::Value(1)),
+        right: Box::new(Expression::Op {
+            op: Operation::Sub,
+            left: Box::new(Expression::Value(2)),
+            right: Box::new(Expression::Value(2)),
+        }),
+    };
+    let e = Expression::Op {
+        op: Operation::Add,
+        left: Box::new(Expression::Value(3)),
+        right: Box::new(div.clone()),
+    };
+    assert_eq!(try_eval(&e), Err(EvalError::DivisionByZero(div)));
+}
+
+#[test]
+fn test_overflow() {
+    let mul = Expression::Op {
+        op: Operation::Mul,
+        left: Box::new(Expression::Value(i64::MAX)),
+        right: Box::new(Expression::Value(2)),
+    };
+    assert_eq!(try_eval(&mul), Err(EvalError::Overflow(mul.clone())));
+
+    let div = Expression::Op {
+        op: Operation::Div,
+        left: Box::new(Expression::Value(i64::MIN)),
+        right: Box::new(Expression::Value(-1)),
+    };
+    assert_eq!(try_eval(&div), Err(EvalError::Overflow(div.clone())));
+}
+
+#[test]
+#[should_panic(expected = "division by zero")]
+fn test_eval_panics() {
+    eval(Expression::Op {
+        op: Operation::Div,
+        left: Box::new(Expression::Value(1)),
+        right: Box::new(Expression::Value(0)),
+    });
+}
+
+#[test]
+fn test_variables() {
+    let e = Expression::Op {
+        op: Operation::Mul,
+        left: Box::new(Expression::Var(String::from("price"))),
+        right: Box::new(Expression::Var(String::from("qty"))),
+    };
+
+    let mut env = Environment::new();
+    env.set("price", 12);
+    env.set("qty", 3);
+    assert_eq!(try_eval_in(&e, &env), Ok(Value::Number(36)));
+
+    env.set("qty", 5);
+    assert_eq!(try_eval_in(&e, &env), Ok(Value::Number(60)));
+}
+
+#[test]
+fn test_unbound_variable() {
+    let mut env = Environment::new();
+    env.set("x", 1);
+    let e = Expression::Op {
+        op: Operation::Add,
+        left: Box::new(Expression::Var(String::from("x"))),
+        right: Box::new(Expression::Var(String::from("y"))),
+    };
+    let err = try_eval_in(&e, &env).unwrap_err();
+    assert_eq!(err, EvalError::UnboundVariable(String::from("y")));
+    assert_eq!(err.to_string(), "unbound variable `y`");
+}
+
+#[test]
+fn test_mod_pow_neg() {
+    let op = |op, left, right| Expression::Op {
+        op,
+        left: Box::new(Expression::Value(left)),
+        right: Box::new(Expression::Value(right)),
+    };
+    assert_eq!(try_eval(&op(Operation::Mod, -7, 3)), Ok(Value::Number(-1)));
+    assert_eq!(try_eval(&op(Operation::Pow, -2, 3)), Ok(Value::Number(-8)));
+    assert_eq!(
+        try_eval(&op(Operation::Pow, -1, 1 << 40)),
+        Ok(Value::Number(1))
+    );
+    assert_eq!(
+        try_eval(&Expression::Neg(Box::new(op(Operation::Pow, 2, 10)))),
+        Ok(Value::Number(-1024))
+    );
+
+    let e = op(Operation::Mod, 1, 0);
+    assert_eq!(try_eval(&e), Err(EvalError::DivisionByZero(e.clone())));
+    let e = op(Operation::Pow, 2, -1);
+    assert_eq!(try_eval(&e), Err(EvalError::NegativeExponent(e.clone())));
+    let e = op(Operation::Pow, 2, 63);
+    assert_eq!(try_eval(&e), Err(EvalError::Overflow(e.clone())));
+    let e = Expression::Neg(Box::new(Expression::Value(i64::MIN)));
+    assert_eq!(try_eval(&e), Err(EvalError::Overflow(e.clone())));
+}
+
+#[test]
+fn test_let_if_fn() {
+    let run = |input: &str| try_eval(&parser::parse(input).unwrap());
+    assert_eq!(
+        run("let x = 3 in let y = x * x in y + x"),
+        Ok(Value::Number(12))
+    );
+    assert_eq!(
+        run("let x = 1 in (let x = 2 in x) + x"),
+        Ok(Value::Number(3))
+    );
+    assert_eq!(run("if 2 < 1 then 1 / 0 else 7"), Ok(Value::Number(7)));
+    assert_eq!(run("1 <= 1 == (2 > 1) != (3 >= 4)"), Ok(Value::Bool(true)));
+    assert_eq!(
+        run("if true == (5 != 5) then 1 else 2"),
+        Ok(Value::Number(2))
+    );
+    assert_eq!(
+        run("fn fact(n) = if n <= 1 then 1 else n * fact(n - 1) in fact(20)"),
+        Ok(Value::Number(2432902008176640000))
+    );
+    assert_eq!(
+        run("fn hyp2(a, b) = a * a + b * b in hyp2(3, 4)"),
+        Ok(Value::Number(25))
+    );
+    // Bodies see the names around their definition, not those around a call.
+    assert_eq!(
+        run("let k = 10 in fn add_k(x) = x + k in let k = 100 in add_k(1)"),
+        Ok(Value::Number(11))
+    );
+    assert_eq!(
+        run("fn f() = 1 in fn g() = f() in fn f() = 2 in g() * 10 + f()"),
+        Ok(Value::Number(12))
+    );
+
+    let e = parser::parse("fn f(x) = x in f(1, 2)").unwrap();
+    let Expression::Function { rest, .. } = &e else {
+        unreachable!()
+    };
+    let err = try_eval(&e).unwrap_err();
+    assert_eq!(
+        err,
+        EvalError::ArgumentCount {
+            call: (**rest).clone(),
+            expected: 1
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "`f(1, 2)` passes the wrong number of arguments, expected 1"
+    );
+    assert_eq!(
+        run("g(1 / 0)"),
+        Err(EvalError::UnknownFunction(String::from("g")))
+    );
+    assert_eq!(
+        run("let x = 1 in y"),
+        Err(EvalError::UnboundVariable(String::from("y")))
+    );
+}
+
+#[test]
+fn test_recursion_limit() {
+    let e = parser::parse("fn down(n) = if n == 0 then 0 else down(n - 1) in down(d)").unwrap();
+    let mut env = Environment::new();
+    env.set("d", 100);
+    assert_eq!(try_eval_in(&e, &env), Ok(Value::Number(0)));
+
+    env.set("d", 200);
+    let err = try_eval_in(&e, &env).unwrap_err();
+    assert_eq!(
+        err,
+        EvalError::RecursionLimit(parser::parse("down(n - 1)").unwrap())
+    );
+    assert_eq!(err.to_string(), "recursion limit exceeded in `down(n - 1)`");
+
+    env.set_max_depth(201);
+    assert_eq!(try_eval_in(&e, &env), Ok(Value::Number(0)));
+    env.set_max_depth(0);
+    assert!(matches!(
+        try_eval_in(&e, &env),
+        Err(EvalError::RecursionLimit(_))
+    ));
+
+    let forever = parser::parse("fn f(x) = f(x) + 1 in f(0)").unwrap();
+    assert!(matches!(
+        try_eval(&forever),
+        Err(EvalError::RecursionLimit(_))
+    ));
+}
+
+#[test]
+fn test_type_mismatch() {
+    let run = |input: &str| try_eval(&parser::parse(input).unwrap());
+    let mismatch = |input: &str, expected, found| EvalError::TypeMismatch {
+        expression: parser::parse(input).unwrap(),
+        expected,
+        found,
+    };
+    assert_eq!(
+        run("1 + (true + 2)"),
+        Err(mismatch("true + 2", Type::Number, Type::Bool))
+    );
+    assert_eq!(
+        run("-(1 < 2)"),
+        Err(mismatch("-(1 < 2)", Type::Number, Type::Bool))
+    );
+    assert_eq!(
+        run("true < false"),
+        Err(mismatch("true < false", Type::Number, Type::Bool))
+    );
+    assert_eq!(
+        run("1 == true"),
+        Err(mismatch("1 == true", Type::Number, Type::Bool))
+    );
+    assert_eq!(
+        run("if 1 then 2 else 3"),
+        Err(mismatch("1", Type::Bool, Type::Number))
+    );
+    // Only the branch taken is evaluated, so its type is all that matters.
+    assert_eq!(run("if false then true + 1 else 3"), Ok(Value::Number(3)));
+
+    let mut env = Environment::new();
+    env.set("flag", true);
+    let e = parser::parse("if flag then 1 else flag + 1").unwrap();
+    assert_eq!(try_eval_in(&e, &env), Ok(Value::Number(1)));
+    env.set("flag", false);
+    let err = try_eval_in(&e, &env).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expected a number, found a boolean in `flag + 1`"
+    );
+}
@@ -0,0 +1,732 @@
+//! A compact representation of [`Expression`]s: nodes live in one arena and
+//! refer to their children by index, and structurally identical
+//! sub-expressions are stored only once.
+//!
+//! A [`Dag`] can hold many expressions at once, which then share whatever
+//! they have in common. Children are always inserted before their parents,
+//! so every node's children have smaller indices than the node itself.
+
+use std::collections::{HashMap, HashSet};
+
+use super::number::{ArithmeticError, Checked};
+use super::{Environment, EvalError, EvalResult, Expression, Operation, Value, negate, operate};
+
+/// The index of a node in a [`Dag`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A node of a [`Dag`], shaped like [`Expression`] but with [`NodeId`]s in
+/// place of boxed children.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Node {
+    Op {
+        op: Operation,
+        left: NodeId,
+        right: NodeId,
+    },
+    Neg(NodeId),
+    Value(i64),
+    Bool(bool),
+    Var(String),
+    Let {
+        name: String,
+        value: NodeId,
+        body: NodeId,
+    },
+    If {
+        condition: NodeId,
+        consequent: NodeId,
+        alternative: NodeId,
+    },
+    Function {
+        name: String,
+        params: Vec<String>,
+        body: NodeId,
+        rest: NodeId,
+    },
+    Call {
+        name: String,
+        args: Vec<NodeId>,
+    },
+}
+
+impl Node {
+    fn children(&self) -> Vec<NodeId> {
+        match self {
+            Node::Op { left, right, .. } => vec![*left, *right],
+            Node::Neg(operand) => vec![*operand],
+            Node::Value(_) | Node::Bool(_) | Node::Var(_) => vec![],
+            Node::Let { value, body, .. } => vec![*value, *body],
+            Node::If {
+                condition,
+                consequent,
+                alternative,
+            } => vec![*condition, *consequent, *alternative],
+            Node::Function { body, rest, .. } => vec![*body, *rest],
+            Node::Call { args, .. } => args.clone(),
+        }
+    }
+}
+
+/// An arena of hash-consed expression nodes.
+#[derive(Debug, Default)]
+pub struct Dag {
+    nodes: Vec<Node>,
+    ids: HashMap<Node, NodeId>,
+}
+
+impl Dag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new DAG holding `e`, and the id of its root.
+    pub fn from_expression(e: &Expression) -> (Self, NodeId) {
+        let mut dag = Self::new();
+        let root = dag.add(e);
+        (dag, root)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.index()]
+    }
+
+    /// The id of `node`, adding it unless an identical node is already here.
+    ///
+    /// # Panics
+    ///
+    /// If a child of `node` is not a node of this DAG.
+    pub fn insert(&mut self, node: Node) -> NodeId {
+        if let Some(&id) = self.ids.get(&node) {
+            return id;
+        }
+        assert!(
+            node.children()
+                .iter()
+                .all(|child| child.index() < self.len()),
+            "children are inserted before their parents"
+        );
+        let id = NodeId(u32::try_from(self.len()).expect("fewer than 2^32 nodes"));
+        self.nodes.push(node.clone());
+        self.ids.insert(node, id);
+        id
+    }
+
+    /// Add `e` and its sub-expressions, returning the id of its root.
+    pub fn add(&mut self, e: &Expression) -> NodeId {
+        let node = match e {
+            Expression::Op { op, left, right } => Node::Op {
+                op: *op,
+                left: self.add(left),
+                right: self.add(right),
+            },
+            Expression::Neg(operand) => Node::Neg(self.add(operand)),
+            Expression::Value(v) => Node::Value(*v),
+            Expression::Bool(b) => Node::Bool(*b),
+            Expression::Var(name) => Node::Var(name.clone()),
+            Expression::Let { name, value, body } => Node::Let {
+                name: name.clone(),
+                value: self.add(value),
+                body: self.add(body),
+            },
+            Expression::If {
+                condition,
+                consequent,
+                alternative,
+            } => Node::If {
+                condition: self.add(condition),
+                consequent: self.add(consequent),
+                alternative: self.add(alternative),
+            },
+            Expression::Function {
+                name,
+                params,
+                body,
+                rest,
+            } => Node::Function {
+                name: name.clone(),
+                params: params.clone(),
+                body: self.add(body),
+                rest: self.add(rest),
+            },
+            Expression::Call { name, args } => Node::Call {
+                name: name.clone(),
+                args: args.iter().map(|arg| self.add(arg)).collect(),
+            },
+        };
+        self.insert(node)
+    }
+
+    /// The tree rooted at `id`, with every shared node copied out in full.
+    pub fn to_expression(&self, id: NodeId) -> Expression {
+        let boxed = |id| Box::new(self.to_expression(id));
+        match self.node(id) {
+            Node::Op { op, left, right } => Expression::Op {
+                op: *op,
+                left: boxed(*left),
+                right: boxed(*right),
+            },
+            Node::Neg(operand) => Expression::Neg(boxed(*operand)),
+            Node::Value(v) => Expression::Value(*v),
+            Node::Bool(b) => Expression::Bool(*b),
+            Node::Var(name) => Expression::Var(name.clone()),
+            Node::Let { name, value, body } => Expression::Let {
+                name: name.clone(),
+                value: boxed(*value),
+                body: boxed(*body),
+            },
+            Node::If {
+                condition,
+                consequent,
+                alternative,
+            } => Expression::If {
+                condition: boxed(*condition),
+                consequent: boxed(*consequent),
+                alternative: boxed(*alternative),
+            },
+            Node::Function {
+                name,
+                params,
+                body,
+                rest,
+            } => Expression::Function {
+                name: name.clone(),
+                params: params.clone(),
+                body: boxed(*body),
+                rest: boxed(*rest),
+            },
+            Node::Call { name, args } => Expression::Call {
+                name: name.clone(),
+                args: args.iter().map(|&arg| self.to_expression(arg)).collect(),
+            },
+        }
+    }
+
+    /// The tree rooted at `id`, with each compound node it uses more than
+    /// once written out only once and bound to a fresh name by a `let`. Unlike
+    /// [`to_expression`](Self::to_expression), this stays about as small as
+    /// the DAG.
+    ///
+    /// A node is bound just inside the innermost `let` body or `fn` around
+    /// its uses, so that the names it refers to keep their meaning.
+    pub fn to_shared_expression(&self, id: NodeId) -> Expression {
+        let mut taken = HashSet::new();
+        for node in &self.nodes {
+            match node {
+                Node::Var(name) | Node::Let { name, .. } | Node::Call { name, .. } => {
+                    taken.insert(name.as_str());
+                }
+                Node::Function { name, params, .. } => {
+                    taken.insert(name.as_str());
+                    taken.extend(params.iter().map(String::as_str));
+                }
+                _ => {}
+            }
+        }
+        Sharing {
+            dag: self,
+            taken,
+            next: 0,
+        }
+        .unit(id)
+    }
+
+    /// Evaluate the expression rooted at `root` like
+    /// [`try_eval_in`](super::try_eval_in) would, with the same result and
+    /// the same errors, except that the expression an error points at is
+    /// given by [`to_shared_expression`](Self::to_shared_expression).
+    ///
+    /// A node whose value depends on nothing but `env` is evaluated at most
+    /// once, however many parents share it. Nodes that refer to a name some
+    /// `let` or parameter in the DAG binds, or that call a function, can mean
+    /// something different in each place they are used, so they are evaluated
+    /// every time.
+    pub fn eval(&self, root: NodeId, env: &Environment) -> EvalResult<Value> {
+        Evaluator::new(self, env).evaluate(root, &Scope::Global, 0)
+    }
+
+    /// Which nodes only depend on the environment, by index.
+    fn context_free(&self) -> Vec<bool> {
+        let mut bound = HashSet::new();
+        for node in &self.nodes {
+            match node {
+                Node::Let { name, .. } => {
+                    bound.insert(name.as_str());
+                }
+                Node::Function { params, .. } => bound.extend(params.iter().map(String::as_str)),
+                _ => {}
+            }
+        }
+        // Children come first, so one pass in index order sees them settled.
+        let mut context_free = Vec::with_capacity(self.len());
+        for node in &self.nodes {
+            let free = match node {
+                Node::Var(name) => !bound.contains(name.as_str()),
+                Node::Function { .. } | Node::Call { .. } => false,
+                _ => node
+                    .children()
+                    .iter()
+                    .all(|child| context_free[child.index()]),
+            };
+            context_free.push(free);
+        }
+        context_free
+    }
+}
+
+/// Writes out parts of a [`Dag`] for [`Dag::to_shared_expression`].
+struct Sharing<'a> {
+    dag: &'a Dag,
+    /// The names the DAG uses, which the `let`s must not shadow.
+    taken: HashSet<&'a str>,
+    next: usize,
+}
+
+impl Sharing<'_> {
+    fn fresh(&mut self) -> String {
+        loop {
+            let name = format!("t{}", self.next);
+            self.next += 1;
+            if !self.taken.contains(name.as_str()) {
+                return name;
+            }
+        }
+    }
+
+    /// The children of `id` that see the same names as `id` itself: all but
+    /// the body of a `let` and both parts of a `fn`.
+    fn same_scope_children(&self, id: NodeId) -> Vec<NodeId> {
+        match self.dag.node(id) {
+            Node::Let { value, .. } => vec![*value],
+            Node::Function { .. } => vec![],
+            node => node.children(),
+        }
+    }
+
+    /// Write out `root`, binding the compound nodes used more than once in
+    /// the same scope as it.
+    fn unit(&mut self, root: NodeId) -> Expression {
+        let mut uses: HashMap<NodeId, usize> = HashMap::new();
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            for child in self.same_scope_children(id) {
+                let count = uses.entry(child).or_default();
+                *count += 1;
+                if *count == 1 {
+                    stack.push(child);
+                }
+            }
+        }
+        let mut shared: Vec<NodeId> = (uses.into_iter())
+            .filter(|&(id, count)| {
+                count > 1
+                    && !matches!(
+                        self.dag.node(id),
+                        Node::Value(_) | Node::Bool(_) | Node::Var(_)
+                    )
+            })
+            .map(|(id, _)| id)
+            .collect();
+        // Children come first, so each `let` only refers to earlier ones.
+        shared.sort_by_key(|id| id.0);
+
+        let mut names = HashMap::new();
+        let mut lets = vec![];
+        for id in shared {
+            let value = self.render(id, &names);
+            let name = self.fresh();
+            names.insert(id, name.clone());
+            lets.push((name, value));
+        }
+        let body = self.render(root, &names);
+        lets.into_iter()
+            .rev()
+            .fold(body, |body, (name, value)| Expression::Let {
+                name,
+                value: Box::new(value),
+                body: Box::new(body),
+            })
+    }
+
+    /// Write out `id`, referring to the nodes in `names` by their names.
+    fn render(&mut self, id: NodeId, names: &HashMap<NodeId, String>) -> Expression {
+        let child = |sharing: &mut Self, id: NodeId| match names.get(&id) {
+            Some(name) => Expression::Var(name.clone()),
+            None => sharing.render(id, names),
+        };
+        match self.dag.node(id) {
+            Node::Op { op, left, right } => Expression::Op {
+                op: *op,
+                left: Box::new(child(self, *left)),
+                right: Box::new(child(self, *right)),
+            },
+            Node::Neg(operand) => Expression::Neg(Box::new(child(self, *operand))),
+            Node::Value(v) => Expression::Value(*v),
+            Node::Bool(b) => Expression::Bool(*b),
+            Node::Var(name) => Expression::Var(name.clone()),
+            Node::Let { name, value, body } => Expression::Let {
+                name: name.clone(),
+                value: Box::new(child(self, *value)),
+                body: Box::new(self.unit(*body)),
+            },
+            Node::If {
+                condition,
+                consequent,
+                alternative,
+            } => Expression::If {
+                condition: Box::new(child(self, *condition)),
+                consequent: Box::new(child(self, *consequent)),
+                alternative: Box::new(child(self, *alternative)),
+            },
+            Node::Function {
+                name,
+                params,
+                body,
+                rest,
+            } => Expression::Function {
+                name: name.clone(),
+                params: params.clone(),
+                body: Box::new(self.unit(*body)),
+                rest: Box::new(self.unit(*rest)),
+            },
+            Node::Call { name, args } => Expression::Call {
+                name: name.clone(),
+                args: args.iter().map(|&arg| child(self, arg)).collect(),
+            },
+        }
+    }
+}
+
+/// The names visible at some node, like the scope of the tree-walking
+/// evaluator but pointing into a [`Dag`].
+enum Scope<'a> {
+    Global,
+    Vars {
+        names: &'a [String],
+        values: Vec<Value>,
+        parent: &'a Scope<'a>,
+    },
+    Function {
+        name: &'a str,
+        params: &'a [String],
+        body: NodeId,
+        parent: &'a Scope<'a>,
+    },
+}
+
+struct Evaluator<'a> {
+    dag: &'a Dag,
+    env: &'a Environment,
+    context_free: Vec<bool>,
+    /// The values of the context-free nodes evaluated so far.
+    memo: Vec<Option<Value>>,
+    /// How many nodes have been evaluated, counting each memoized one once.
+    steps: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(dag: &'a Dag, env: &'a Environment) -> Self {
+        Self {
+            dag,
+            env,
+            context_free: dag.context_free(),
+            memo: vec![None; dag.len()],
+            steps: 0,
+        }
+    }
+
+    fn evaluate(&mut self, id: NodeId, scope: &Scope<'_>, calls: usize) -> EvalResult<Value> {
+        if let Some(value) = self.memo[id.index()] {
+            return Ok(value);
+        }
+        self.steps += 1;
+        let dag = self.dag;
+        let at = |err: ArithmeticError| err.at(&dag.to_shared_expression(id));
+        let value = match dag.node(id) {
+            Node::Op { op, left, right } => {
+                let left = self.evaluate(*left, scope, calls)?;
+                let right = self.evaluate(*right, scope, calls)?;
+                operate(&Checked, *op, left, right).map_err(at)?
+            }
+            Node::Neg(operand) => {
+                let operand = self.evaluate(*operand, scope, calls)?;
+                negate(&Checked, operand).map_err(at)?
+            }
+            Node::Value(v) => Value::Number(*v),
+            Node::Bool(b) => Value::Bool(*b),
+            Node::Var(name) => self
+                .var(scope, name)
+                .ok_or_else(|| EvalError::UnboundVariable(name.clone()))?,
+            Node::Let { name, value, body } => {
+                let value = self.evaluate(*value, scope, calls)?;
+                let scope = Scope::Vars {
+                    names: std::slice::from_ref(name),
+                    values: vec![value],
+                    parent: scope,
+                };
+                self.evaluate(*body, &scope, calls)?
+            }
+            Node::If {
+                condition,
+                consequent,
+                alternative,
+            } => {
+                let taken = self.evaluate(*condition, scope, calls)?.boolean();
+                let taken = taken.map_err(|err| err.at(&dag.to_shared_expression(*condition)))?;
+                let branch = if taken { consequent } else { alternative };
+                self.evaluate(*branch, scope, calls)?
+            }
+            Node::Function {
+                name,
+                params,
+                body,
+                rest,
+            } => {
+                let scope = Scope::Function {
+                    name,
+                    params,
+                    body: *body,
+                    parent: scope,
+                };
+                self.evaluate(*rest, &scope, calls)?
+            }
+            Node::Call { args, .. } => {
+                let function = self.function(scope, id)?;
+                let args = args
+                    .iter()
+                    .map(|&arg| self.evaluate(arg, scope, calls))
+                    .collect::<EvalResult<_>>()?;
+                let Scope::Function { params, body, .. } = function else {
+                    unreachable!("calls are resolved to functions");
+                };
+                if calls >= self.env.max_depth() {
+                    return Err(EvalError::RecursionLimit(dag.to_shared_expression(id)));
+                }
+                let scope = Scope::Vars {
+                    names: params,
+                    values: args,
+                    parent: function,
+                };
+                self.evaluate(*body, &scope, calls + 1)?
+            }
+        };
+        if self.context_free[id.index()] {
+            self.memo[id.index()] = Some(value);
+        }
+        Ok(value)
+    }
+
+    fn var(&self, mut scope: &Scope<'_>, name: &str) -> Option<Value> {
+        loop {
+            scope = match scope {
+                Scope::Global => return self.env.get(name),
+                Scope::Vars {
+                    names,
+                    values,
+                    parent,
+                } => match names.iter().rposition(|n| n == name) {
+                    Some(i) => return Some(values[i]),
+                    None => parent,
+                },
+                Scope::Function { parent, .. } => parent,
+            };
+        }
+    }
+
+    /// The `Scope::Function` that the call `id` refers to, checking that it
+    /// takes as many arguments as the call passes.
+    fn function<'s>(&self, mut scope: &'s Scope<'s>, id: NodeId) -> EvalResult<&'s Scope<'s>> {
+        let Node::Call { name, args } = self.dag.node(id) else {
+            unreachable!("only calls refer to functions");
+        };
+        loop {
+            scope = match scope {
+                Scope::Global => return Err(EvalError::UnknownFunction(name.clone())),
+                Scope::Function {
+                    name: n, params, ..
+                } if n == name => {
+                    if params.len() != args.len() {
+                        return Err(EvalError::ArgumentCount {
+                            call: self.dag.to_shared_expression(id),
+                            expected: params.len(),
+                        });
+                    }
+                    return Ok(scope);
+                }
+                Scope::Vars { parent, .. } | Scope::Function { parent, .. } => parent,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern_matching::parser::parse;
+    use crate::pattern_matching::try_eval_in;
+
+    fn env() -> Environment {
+        let mut env = Environment::new();
+        env.set("x", 7);
+        env.set("y", -3);
+        env
+    }
+
+    #[test]
+    fn test_sharing() {
+        let e = parse("(x * y + 1) * (x * y + 1) - x").unwrap();
+        let (dag, root) = Dag::from_expression(&e);
+        // x, y, x * y, 1, x * y + 1, the product and the difference.
+        assert_eq!(dag.len(), 7);
+        assert_eq!(dag.to_expression(root), e);
+
+        let mut dag = Dag::new();
+        let a = dag.add(&parse("x * y + 1").unwrap());
+        let b = dag.add(&parse("1 + (x * y + 1)").unwrap());
+        assert_eq!(dag.len(), 6);
+        let one = dag.insert(Node::Value(1));
+        assert_eq!(dag.len(), 6);
+        assert_eq!(
+            dag.node(b),
+            &Node::Op {
+                op: Operation::Add,
+                left: one,
+                right: a,
+            }
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for input in [
+            "(3 - 4) * 5 + 10 * 9",
+            "-x ^ 2 % 3 == -(x ^ 2)",
+            "let a = x in let a = a + a in a * a",
+            "if true then x else -x",
+            "fn f(a, b) = a - b in f(f(1, 2), f(1, 2))",
+        ] {
+            let e = parse(input).unwrap();
+            let (dag, root) = Dag::from_expression(&e);
+            assert_eq!(dag.to_expression(root), e, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_shared_expression() {
+        for (input, expected) in [
+            ("(x * y + 1) * (x * y + 1)", "let t0 = x * y + 1 in t0 * t0"),
+            ("(t0 + 1) - (t0 + 1) * 2", "let t1 = t0 + 1 in t1 - t1 * 2"),
+            (
+                "(x + x) * (let x = 1 in (x + x) / (x + x))",
+                "(x + x) * (let x = 1 in let t0 = x + x in t0 / t0)",
+            ),
+            (
+                "fn f(a) = -a * -a in f(-a) + -a",
+                "fn f(a) = (let t0 = -a in t0 * t0) in let t1 = -a in f(t1) + t1",
+            ),
+            ("x + x - 1", "x + x - 1"),
+        ] {
+            let (dag, root) = Dag::from_expression(&parse(input).unwrap());
+            let shared = dag.to_shared_expression(root);
+            assert_eq!(shared, parse(expected).unwrap(), "{input}");
+            assert_eq!(
+                try_eval_in(&shared, &env()),
+                try_eval_in(&dag.to_expression(root), &env()),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_matches_try_eval_in() {
+        for input in [
+            "(3 - 4) * 5 + 10 * 9",
+            "(x + y) * (x + y) - (x + y) / (x + y)",
+            "let x = x + 1 in x * x + (let x = 2 in x * x) + x * x",
+            "let a = x * x in fn f(b) = a + b * b in f(2) + f(3) + (x * x + 2 * 2)",
+            "fn fact(n) = if n <= 1 then 1 else n * fact(n - 1) in fact(x) - fact(x - 1)",
+            "fn f() = 1 in fn g() = f() in fn f() = 2 in g() * 10 + f()",
+            "if x > y == true then x / (y + 3) else 0",
+            "(x - 7) ^ -1 + (x - 7) ^ -1",
+            "fn f(x) = x in f(1, 2) + f(1, 2)",
+            "fn f(n) = f(n) + 1 in f(0)",
+            "if x then 1 else 2",
+            "unbound + unbound",
+        ] {
+            let e = parse(input).unwrap();
+            let (dag, root) = Dag::from_expression(&e);
+            assert_eq!(dag.eval(root, &env()), try_eval_in(&e, &env()), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_shared_nodes_evaluate_once() {
+        // `x + x`, then that doubled, sixty times over: a tree of 2^61 - 1
+        // nodes, but a DAG of only 61.
+        let mut dag = Dag::new();
+        let mut doubled = dag.insert(Node::Var(String::from("x")));
+        for _ in 0..60 {
+            doubled = dag.insert(Node::Op {
+                op: Operation::Add,
+                left: doubled,
+                right: doubled,
+            });
+        }
+        assert_eq!(dag.len(), 61);
+        let mut env = Environment::new();
+        env.set("x", 1);
+        let mut evaluator = Evaluator::new(&dag, &env);
+        assert_eq!(
+            evaluator.evaluate(doubled, &Scope::Global, 0),
+            Ok(Value::Number(1 << 60))
+        );
+        assert_eq!(evaluator.steps, 61);
+
+        // Three more doublings overflow, and the error keeps the sharing.
+        for _ in 0..3 {
+            doubled = dag.insert(Node::Op {
+                op: Operation::Add,
+                left: doubled,
+                right: doubled,
+            });
+        }
+        let Err(EvalError::Overflow(e)) = dag.eval(doubled, &env) else {
+            panic!("2^63 fits in an i64");
+        };
+        assert!(
+            e.to_string()
+                .starts_with("let t0 = x + x in let t1 = t0 + t0 in")
+        );
+        assert!(e.to_string().ends_with("in t61 + t61"), "{e}");
+
+        // Bound names can mean different things in different places, so they
+        // are not memoized.
+        let e = parse("(let x = 3 in x + x) + (let y = 2 in x + x)").unwrap();
+        let (dag, root) = Dag::from_expression(&e);
+        assert_eq!(dag.eval(root, &env), Ok(Value::Number(8)));
+        let e = parse("(let x = 1 in y + y) + (y + y)").unwrap();
+        let (dag, root) = Dag::from_expression(&e);
+        env.set("y", 5);
+        let mut counting = Evaluator::new(&dag, &env);
+        assert_eq!(
+            counting.evaluate(root, &Scope::Global, 0),
+            Ok(Value::Number(20))
+        );
+        // y, y + y, 1, the let, and the sum; the second y + y is remembered.
+        assert_eq!(counting.steps, 5);
+    }
+}
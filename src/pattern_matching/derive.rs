@@ -0,0 +1,292 @@
+//! Symbolic differentiation of an [`Expression`] with respect to a variable.
+//!
+//! The result is built with the usual sum, product, quotient and power rules
+//! and tidied up as it is built, so `x * 1`, `0 + x` and friends never appear
+//! in it. Only additions, subtractions and multiplications of literals are
+//! folded; those agree in every [`Mode`](super::number::Mode), whereas folding
+//! a division would not.
+
+use std::fmt;
+
+use super::{Expression, Operation};
+
+/// Differentiating would need a rule that is not supported, such as for
+/// `2 ^ x`, where the exponent depends on the variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeriveError {
+    /// The sub-expression that could not be differentiated.
+    pub expression: Expression,
+    pub var: String,
+}
+
+impl fmt::Display for DeriveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot differentiate `{}` with respect to `{}`",
+            self.expression, self.var
+        )
+    }
+}
+
+impl std::error::Error for DeriveError {}
+
+/// The derivative of `e` with respect to the variable `var`.
+///
+/// Every other variable is treated as a constant. `u % v` is differentiated
+/// as `u` and `if` branch by branch, which holds wherever they are
+/// continuous; `u ^ v` only when `v` does not depend on `var`. Booleans,
+/// comparisons included, have no derivative, and `let`, `fn` and calls are
+/// not supported.
+pub fn derive(e: &Expression, var: &str) -> Result<Expression, DeriveError> {
+    let unsupported = || DeriveError {
+        expression: e.clone(),
+        var: var.to_string(),
+    };
+    let derivative = match e {
+        Expression::Op { op, left, right } => {
+            let (u, v) = (&**left, &**right);
+            match op {
+                Operation::Add => add(derive(u, var)?, derive(v, var)?),
+                Operation::Sub => sub(derive(u, var)?, derive(v, var)?),
+                Operation::Mul => add(
+                    mul(derive(u, var)?, v.clone()),
+                    mul(u.clone(), derive(v, var)?),
+                ),
+                Operation::Div => match derive(v, var)? {
+                    Expression::Value(0) => div(derive(u, var)?, v.clone()),
+                    dv => div(
+                        sub(mul(derive(u, var)?, v.clone()), mul(u.clone(), dv)),
+                        pow(v.clone(), Expression::Value(2)),
+                    ),
+                },
+                Operation::Mod if !depends_on(v, var) => derive(u, var)?,
+                Operation::Pow if !depends_on(v, var) => mul(
+                    mul(
+                        v.clone(),
+                        pow(u.clone(), sub(v.clone(), Expression::Value(1))),
+                    ),
+                    derive(u, var)?,
+                ),
+                Operation::Mod | Operation::Pow | Operation::Compare(_) => {
+                    return Err(unsupported());
+                }
+            }
+        }
+        Expression::Neg(operand) => neg(derive(operand, var)?),
+        Expression::Value(_) => Expression::Value(0),
+        Expression::Var(name) => Expression::Value((name == var).into()),
+        Expression::If {
+            condition,
+            consequent,
+            alternative,
+        } => Expression::If {
+            condition: condition.clone(),
+            consequent: Box::new(derive(consequent, var)?),
+            alternative: Box::new(derive(alternative, var)?),
+        },
+        Expression::Bool(_)
+        | Expression::Let { .. }
+        | Expression::Function { .. }
+        | Expression::Call { .. } => return Err(unsupported()),
+    };
+    Ok(derivative)
+}
+
+/// Whether the variable `var` occurs anywhere in `e`. Bindings and calls are
+/// assumed to depend on it, since a function body may refer to it.
+fn depends_on(e: &Expression, var: &str) -> bool {
+    match e {
+        Expression::Op { left, right, .. } => depends_on(left, var) || depends_on(right, var),
+        Expression::Neg(operand) => depends_on(operand, var),
+        Expression::Value(_) | Expression::Bool(_) => false,
+        Expression::Var(name) => name == var,
+        Expression::If {
+            condition,
+            consequent,
+            alternative,
+        } => [condition, consequent, alternative]
+            .iter()
+            .any(|e| depends_on(e, var)),
+        Expression::Let { .. } | Expression::Function { .. } | Expression::Call { .. } => true,
+    }
+}
+
+fn node(op: Operation, left: Expression, right: Expression) -> Expression {
+    Expression::Op {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+fn add(left: Expression, right: Expression) -> Expression {
+    match (left, right) {
+        (Expression::Value(l), Expression::Value(r)) if l.checked_add(r).is_some() => {
+            Expression::Value(l + r)
+        }
+        (x, Expression::Value(0)) | (Expression::Value(0), x) => x,
+        (left, right) => node(Operation::Add, left, right),
+    }
+}
+
+fn sub(left: Expression, right: Expression) -> Expression {
+    match (left, right) {
+        (Expression::Value(l), Expression::Value(r)) if l.checked_sub(r).is_some() => {
+            Expression::Value(l - r)
+        }
+        (x, Expression::Value(0)) => x,
+        (Expression::Value(0), x) => neg(x),
+        (left, right) => node(Operation::Sub, left, right),
+    }
+}
+
+/// Multiply, keeping a literal factor on the left as in `3 * x`.
+fn mul(left: Expression, right: Expression) -> Expression {
+    match (left, right) {
+        (Expression::Value(l), Expression::Value(r)) if l.checked_mul(r).is_some() => {
+            Expression::Value(l * r)
+        }
+        (_, Expression::Value(0)) | (Expression::Value(0), _) => Expression::Value(0),
+        (x, Expression::Value(1)) | (Expression::Value(1), x) => x,
+        (x, Expression::Value(-1)) | (Expression::Value(-1), x) => neg(x),
+        (x, Expression::Value(v)) => node(Operation::Mul, Expression::Value(v), x),
+        (left, right) => node(Operation::Mul, left, right),
+    }
+}
+
+fn div(left: Expression, right: Expression) -> Expression {
+    match (left, right) {
+        (x, Expression::Value(1)) => x,
+        (Expression::Value(0), _) => Expression::Value(0),
+        (left, right) => node(Operation::Div, left, right),
+    }
+}
+
+fn pow(base: Expression, exponent: Expression) -> Expression {
+    match (base, exponent) {
+        (x, Expression::Value(1)) => x,
+        (_, Expression::Value(0)) => Expression::Value(1),
+        (base, exponent) => node(Operation::Pow, base, exponent),
+    }
+}
+
+fn neg(mut e: Expression) -> Expression {
+    match &mut e {
+        Expression::Value(v) if *v != i64::MIN => Expression::Value(-*v),
+        Expression::Neg(operand) => operand.take(),
+        _ => Expression::Neg(Box::new(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern_matching::number::{Mode, Number, eval_number};
+    use crate::pattern_matching::parser::parse;
+    use crate::pattern_matching::{Environment, Value};
+
+    fn check(input: &str, expected: &str) {
+        let derivative = derive(&parse(input).unwrap(), "x").unwrap();
+        assert_eq!(derivative.to_string(), expected, "d/dx {input}");
+    }
+
+    /// Replace every `x` in `e` with `x + by`.
+    fn shift(e: &Expression, by: &Expression) -> Expression {
+        match e {
+            Expression::Op { op, left, right } => node(*op, shift(left, by), shift(right, by)),
+            Expression::Neg(operand) => Expression::Neg(Box::new(shift(operand, by))),
+            Expression::Var(name) if name == "x" => add(e.clone(), by.clone()),
+            Expression::If {
+                condition,
+                consequent,
+                alternative,
+            } => Expression::If {
+                condition: Box::new(shift(condition, by)),
+                consequent: Box::new(shift(consequent, by)),
+                alternative: Box::new(shift(alternative, by)),
+            },
+            _ => e.clone(),
+        }
+    }
+
+    fn eval_float(e: &Expression, env: &Environment) -> f64 {
+        match eval_number(e, env, Mode::Float) {
+            Ok(Value::Number(Number::Float(v))) => v,
+            other => panic!("evaluating {e} gave {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_rules() {
+        check("42", "0");
+        check("y", "0");
+        check("x", "1");
+        check("3 * x + 2", "3");
+        check("x * y - y", "y");
+        check("x * x", "x + x");
+        check("x ^ 3", "3 * x ^ 2");
+        check("(2 * x + 1) ^ 2", "2 * (2 * (2 * x + 1))");
+        check("-x", "-1");
+        check("-(x * x)", "-(x + x)");
+        check("x / 3", "1 / 3");
+        check("1 / x", "-1 / x ^ 2");
+        check("x / (x + 1)", "(x + 1 - x) / (x + 1) ^ 2");
+        check("x ^ y", "y * x ^ (y - 1)");
+        check("x % 7", "1");
+        check("if x < 0 then -x else x * x", "if x < 0 then -1 else x + x");
+    }
+
+    #[test]
+    fn test_unsupported() {
+        for input in [
+            "2 ^ x",
+            "x ^ x",
+            "7 % x",
+            "1 + y ^ (x * 2)",
+            "let y = x in y",
+            "x < 1",
+            "if true then x else false",
+        ] {
+            let e = parse(input).unwrap();
+            let err = derive(&e, "x").unwrap_err();
+            assert_eq!(err.var, "x");
+            assert!(input.contains(&err.expression.to_string()), "{err}");
+        }
+        assert!(derive(&parse("2 ^ x").unwrap(), "y").is_ok());
+    }
+
+    #[test]
+    fn test_finite_differences() {
+        let h = parse("1 / 10000").unwrap();
+        let minus_h = parse("-1 / 10000").unwrap();
+        for input in [
+            "3 * x + 2",
+            "x * x * x - 4 * x",
+            "(x + y) * (x - 2 * y)",
+            "x ^ 5 - x ^ 2 / 3",
+            "1 / x",
+            "x / (x * x + 1)",
+            "(y - x) / (x + 5) ^ 2",
+            "-(x * y) ^ 3",
+            "x ^ -2 + y ^ 2",
+            "x ^ y",
+            "if x < 0 then -x else x * x * y",
+        ] {
+            let e = parse(input).unwrap();
+            let derivative = derive(&e, "x").unwrap();
+            let (above, below) = (shift(&e, &h), shift(&e, &minus_h));
+            for x in [-3, -2, -1, 1, 2, 3, 7] {
+                let mut env = Environment::new();
+                env.set("x", x);
+                env.set("y", 2);
+                let exact = eval_float(&derivative, &env);
+                let estimate = (eval_float(&above, &env) - eval_float(&below, &env)) / 2e-4;
+                assert!(
+                    (exact - estimate).abs() <= 1e-4 * exact.abs().max(1.0),
+                    "d/dx {input} at x = {x}: {derivative} is {exact}, estimated {estimate}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Human readable renderings of an [`Expression`]: infix notation with only
+//! the parentheses it needs, and S-expressions for debugging.
+
+use std::fmt;
+
+use super::{Comparison, Expression, NEG_PRECEDENCE, Operation};
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Operation::Add => "+",
+            Operation::Sub => "-",
+            Operation::Mul => "*",
+            Operation::Div => "/",
+            Operation::Mod => "%",
+            Operation::Pow => "^",
+            Operation::Compare(c) => match c {
+                Comparison::Lt => "<",
+                Comparison::Le => "<=",
+                Comparison::Gt => ">",
+                Comparison::Ge => ">=",
+                Comparison::Eq => "==",
+                Comparison::Ne => "!=",
+            },
+        };
+        f.write_str(symbol)
+    }
+}
+
+/// Infix notation that [`parse`](super::parser::parse) reads back into the
+/// same tree.
+///
+/// An operand is parenthesized only when precedence or associativity would
+/// otherwise regroup it: `(1 + 2) * 3`, but `1 + 2 * 3`. An operand of equal
+/// precedence on the side an operation does not associate to keeps its
+/// parentheses, as in `1 - (2 - 3)`, `1 + (2 + 3)` or `(2 ^ 3) ^ 2`.
+/// `let`, `if` and `fn` are parenthesized whenever they are an operand.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Op { .. } => {
+                // Down the left operands for as long as they need no
+                // parentheses, so that a long chain prints in a loop.
+                let mut spine = vec![];
+                let mut bottom = self;
+                let parens = loop {
+                    let Expression::Op { op, left, right } = bottom else {
+                        break false;
+                    };
+                    spine.push((*op, right));
+                    bottom = left;
+                    if parens_left(*op, left) {
+                        break true;
+                    }
+                };
+                write_operand(f, bottom, parens)?;
+                for (op, right) in spine.into_iter().rev() {
+                    write!(f, " {op} ")?;
+                    write_operand(f, right, parens_right(op, right))?;
+                }
+                Ok(())
+            }
+            Expression::Neg(operand) => {
+                // `-(3)` rather than `-3`, which reads back as a literal.
+                let parens = binding(operand) < NEG_PRECEDENCE
+                    || matches!(**operand, Expression::Value(v) if v >= 0);
+                f.write_str("-")?;
+                write_operand(f, operand, parens)
+            }
+            Expression::Value(v) => write!(f, "{v}"),
+            Expression::Bool(b) => write!(f, "{b}"),
+            Expression::Var(name) => f.write_str(name),
+            Expression::Let { name, value, body } => write!(f, "let {name} = {value} in {body}"),
+            Expression::If {
+                condition,
+                consequent,
+                alternative,
+            } => write!(f, "if {condition} then {consequent} else {alternative}"),
+            Expression::Function {
+                name,
+                params,
+                body,
+                rest,
+            } => write!(f, "fn {name}({}) = {body} in {rest}", params.join(", ")),
+            Expression::Call { name, args } => {
+                write!(f, "{name}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+/// Whether `left`, the left operand of `op`, needs parentheses.
+fn parens_left(op: Operation, left: &Expression) -> bool {
+    let precedence = op.precedence();
+    binding(left) < precedence || (binding(left) == precedence && op.is_right_associative())
+}
+
+/// Whether `right`, the right operand of `op`, needs parentheses.
+fn parens_right(op: Operation, right: &Expression) -> bool {
+    let precedence = op.precedence();
+    // A leading `-` cannot be regrouped with what precedes it.
+    !is_negative(right)
+        && (binding(right) < precedence
+            || (binding(right) == precedence && !op.is_right_associative()))
+}
+
+/// How tightly `e` holds together when printed, on the same scale as
+/// [`Operation::precedence`].
+fn binding(e: &Expression) -> u8 {
+    match e {
+        Expression::Op { op, .. } => op.precedence(),
+        Expression::Let { .. } | Expression::If { .. } | Expression::Function { .. } => 0,
+        _ if is_negative(e) => NEG_PRECEDENCE,
+        _ => u8::MAX,
+    }
+}
+
+/// Whether `e` prints with a leading `-`.
+fn is_negative(e: &Expression) -> bool {
+    matches!(e, Expression::Neg(_)) || matches!(e, Expression::Value(v) if *v < 0)
+}
+
+fn write_operand(f: &mut fmt::Formatter<'_>, e: &Expression, parens: bool) -> fmt::Result {
+    if parens {
+        write!(f, "({e})")
+    } else {
+        write!(f, "{e}")
+    }
+}
+
+/// Renders an expression as an S-expression, e.g. `(+ (* 10 9) x)`.
+///
+/// Created by [`Expression::sexpr`].
+pub struct SExpr<'a>(&'a Expression);
+
+impl Expression {
+    pub fn sexpr(&self) -> SExpr<'_> {
+        SExpr(self)
+    }
+}
+
+impl fmt::Display for SExpr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Expression::Op { .. } => {
+                let (spine, bottom) = self.0.left_spine();
+                for (op, _, _) in &spine {
+                    write!(f, "({op} ")?;
+                }
+                write!(f, "{}", bottom.sexpr())?;
+                for (_, _, right) in spine.into_iter().rev() {
+                    write!(f, " {})", right.sexpr())?;
+                }
+                Ok(())
+            }
+            Expression::Neg(operand) => write!(f, "(- {})", operand.sexpr()),
+            Expression::Let { name, value, body } => {
+                write!(f, "(let {name} {} {})", value.sexpr(), body.sexpr())
+            }
+            Expression::If {
+                condition,
+                consequent,
+                alternative,
+            } => write!(
+                f,
+                "(if {} {} {})",
+                condition.sexpr(),
+                consequent.sexpr(),
+                alternative.sexpr()
+            ),
+            Expression::Function {
+                name,
+                params,
+                body,
+                rest,
+            } => write!(
+                f,
+                "(fn {name} ({}) {} {})",
+                params.join(" "),
+                body.sexpr(),
+                rest.sexpr()
+            ),
+            Expression::Call { name, args } => {
+                write!(f, "({name}")?;
+                for arg in args {
+                    write!(f, " {}", arg.sexpr())?;
+                }
+                f.write_str(")")
+            }
+            leaf => write!(f, "{leaf}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern_matching::parser::parse;
+
+    /// Check that `input` prints as `expected` and that this reads back into
+    /// the same tree.
+    fn check(input: &str, expected: &str) {
+        let e = parse(input).unwrap();
+        let printed = e.to_string();
+        assert_eq!(printed, expected);
+        assert_eq!(parse(&printed).unwrap(), e);
+    }
+
+    #[test]
+    fn test_minimal_parentheses() {
+        check("(3 - 4) * 5 + 10 * 9", "(3 - 4) * 5 + 10 * 9");
+        check("((1 + 2)) + 3", "1 + 2 + 3");
+        check("1 + (2 * 3)", "1 + 2 * 3");
+        check("(1 * 2) / (3 * 4)", "1 * 2 / (3 * 4)");
+        check("x-(y-z)", "x - (y - z)");
+        check("x+(y+z)", "x + (y + z)");
+        check("8 / (4 / 2)", "8 / (4 / 2)");
+        check("(rate)", "rate");
+    }
+
+    #[test]
+    fn test_negative_literals() {
+        check("3 - -4", "3 - -4");
+        check("-1 * 5", "-1 * 5");
+        check("-9223372036854775808", "-9223372036854775808");
+    }
+
+    #[test]
+    fn test_mod_pow_neg() {
+        check("2 ^ 3 ^ 2", "2 ^ 3 ^ 2");
+        check("(2 ^ 3) ^ 2", "(2 ^ 3) ^ 2");
+        check("-2 ^ 2", "-2 ^ 2");
+        check("(-2) ^ 2", "(-2) ^ 2");
+        check("2 ^ -x", "2 ^ -x");
+        check("-(x + 1) * y % 3", "-(x + 1) * y % 3");
+        check("x % (y * 3)", "x % (y * 3)");
+        check("-(3)", "-(3)");
+        check("- -3", "--3");
+        check("--x", "--x");
+        check("-x ^ 2", "-x ^ 2");
+        check("(-x) ^ 2", "(-x) ^ 2");
+        check("(-(-2)) ^ x", "(--2) ^ x");
+        check("1 - -x", "1 - -x");
+    }
+
+    #[test]
+    fn test_let_if_fn() {
+        check("let x = 1 in x + 1", "let x = 1 in x + 1");
+        check("(let x = 1 in x) + 1", "(let x = 1 in x) + 1");
+        check("1 + (let x = 1 in x)", "1 + (let x = 1 in x)");
+        check("let y = let x = 1 in x in y", "let y = let x = 1 in x in y");
+        check(
+            "if a<b then a else if b < c then b else c",
+            "if a < b then a else if b < c then b else c",
+        );
+        check("-(if x then 1 else 2)", "-(if x then 1 else 2)");
+        check("(true)==(x<1)", "true == (x < 1)");
+        check(
+            "fn f(a,b) = a*b in f(1, f(2,3))",
+            "fn f(a, b) = a * b in f(1, f(2, 3))",
+        );
+        check("fn zero() = 0 in zero() * 2", "fn zero() = 0 in zero() * 2");
+        check("(x + 1 == y) != (x <= 2)", "x + 1 == y != (x <= 2)");
+        check("x < y - 1", "x < y - 1");
+        check("(x < y) - 1", "(x < y) - 1");
+    }
+
+    #[test]
+    fn test_sexpr() {
+        let e = parse("(3 - x) * 5 + 10 * -9").unwrap();
+        assert_eq!(e.sexpr().to_string(), "(+ (* (- 3 x) 5) (* 10 -9))");
+        assert_eq!(Expression::Value(7).sexpr().to_string(), "7");
+        let e = parse("-x ^ 2").unwrap();
+        assert_eq!(e.sexpr().to_string(), "(- (^ x 2))");
+        let e = parse("x == false").unwrap();
+        assert_eq!(e.sexpr().to_string(), "(== x false)");
+        let e = parse("fn f(a, b) = if a < b then a else b in let x = 2 in f(x, 3)").unwrap();
+        assert_eq!(
+            e.sexpr().to_string(),
+            "(fn f (a b) (if (< a b) a b) (let x 2 (f x 3)))"
+        );
+    }
+}
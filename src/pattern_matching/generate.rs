@@ -0,0 +1,384 @@
+//! Seeded random [`Expression`]s, and a small property-testing harness built
+//! on them that shrinks failures to a minimal counterexample.
+//!
+//! The same seed and [`Config`] always give the same trees, so a failure
+//! found once can be replayed.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use super::{Expression, Operation};
+use crate::random::Rng;
+
+/// The shape of the trees a [`Generator`] builds.
+///
+/// Only arithmetic is generated. Comparisons may be listed in `operations`,
+/// but since arithmetic does not accept their booleans, most trees with one
+/// will then be ill-typed.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// How many operations deep a tree may nest.
+    pub max_depth: u32,
+    /// The operations to build, each with a relative weight.
+    pub operations: Vec<(Operation, u32)>,
+    /// The weight of negation, relative to those of `operations`.
+    pub negation: u32,
+    /// The chance, in percent, that a node above `max_depth` is a leaf anyway.
+    pub leaf_percent: u32,
+    /// The chance, in percent, that a leaf is a variable rather than a literal.
+    pub variable_percent: u32,
+    pub literals: RangeInclusive<i64>,
+    pub variables: Vec<String>,
+}
+
+impl Default for Config {
+    /// Every arithmetic operation, equally likely, over small literals and
+    /// the variables `x` and `y`.
+    fn default() -> Self {
+        Self {
+            max_depth: 6,
+            operations: [
+                Operation::Add,
+                Operation::Sub,
+                Operation::Mul,
+                Operation::Div,
+                Operation::Mod,
+                Operation::Pow,
+            ]
+            .map(|op| (op, 1))
+            .to_vec(),
+            negation: 1,
+            leaf_percent: 25,
+            variable_percent: 20,
+            literals: -10..=10,
+            variables: vec![String::from("x"), String::from("y")],
+        }
+    }
+}
+
+/// Builds random trees as described by a [`Config`].
+pub struct Generator {
+    rng: Rng,
+    config: Config,
+    /// The operations to pick from, with `None` standing for negation.
+    choices: Vec<(Option<Operation>, u32)>,
+}
+
+impl Generator {
+    pub fn new(seed: u64, config: Config) -> Self {
+        let mut choices: Vec<_> = (config.operations.iter())
+            .map(|&(op, weight)| (Some(op), weight))
+            .collect();
+        choices.push((None, config.negation));
+        Self {
+            rng: Rng::new(seed),
+            config,
+            choices,
+        }
+    }
+
+    pub fn expression(&mut self) -> Expression {
+        self.tree(self.config.max_depth)
+    }
+
+    fn tree(&mut self, depth: u32) -> Expression {
+        let can_nest = self.choices.iter().any(|&(_, weight)| weight > 0);
+        if depth == 0 || !can_nest || self.rng.chance(self.config.leaf_percent) {
+            return self.leaf();
+        }
+        match *self.rng.weighted(&self.choices) {
+            Some(op) => Expression::Op {
+                op,
+                left: Box::new(self.tree(depth - 1)),
+                right: Box::new(self.tree(depth - 1)),
+            },
+            None => Expression::Neg(Box::new(self.tree(depth - 1))),
+        }
+    }
+
+    fn leaf(&mut self) -> Expression {
+        let variables = &self.config.variables;
+        if !variables.is_empty() && self.rng.chance(self.config.variable_percent) {
+            let i = self.rng.below(variables.len() as u64) as usize;
+            return Expression::Var(variables[i].clone());
+        }
+        Expression::Value(self.rng.in_range(self.config.literals.clone()))
+    }
+}
+
+/// A generated tree a property does not hold for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    /// Which of the generated trees failed, counting from zero.
+    pub case: usize,
+    pub original: Expression,
+    /// The smallest tree derived from `original` that still fails.
+    pub shrunk: Expression,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "property fails for `{}` (case {}, shrunk from `{}`)",
+            self.shrunk, self.case, self.original
+        )
+    }
+}
+
+/// Check `property` against `cases` trees generated from `seed`, and shrink
+/// the first one it fails for.
+pub fn find_counterexample(
+    config: Config,
+    seed: u64,
+    cases: usize,
+    mut property: impl FnMut(&Expression) -> bool,
+) -> Option<Counterexample> {
+    let mut generator = Generator::new(seed, config);
+    for case in 0..cases {
+        let e = generator.expression();
+        if !property(&e) {
+            let shrunk = shrink(&e, |e| !property(e));
+            return Some(Counterexample {
+                case,
+                original: e,
+                shrunk,
+            });
+        }
+    }
+    None
+}
+
+/// Make `e` as small as possible while `fails` still holds for it.
+///
+/// Each step tries replacing an operation by one of its operands, a
+/// variable by `0`, or a literal by `0` or half of itself, keeping the first
+/// change that still fails, until none does. Every change makes the tree
+/// strictly simpler, so this ends. Only arithmetic is shrunk; `let`, `if`,
+/// functions and booleans are kept as they are.
+pub fn shrink(e: &Expression, mut fails: impl FnMut(&Expression) -> bool) -> Expression {
+    let mut e = e.clone();
+    'simpler: loop {
+        for candidate in simpler(&e) {
+            if fails(&candidate) {
+                e = candidate;
+                continue 'simpler;
+            }
+        }
+        return e;
+    }
+}
+
+/// The trees one shrinking step away from `e`, most drastic first.
+fn simpler(e: &Expression) -> Vec<Expression> {
+    match e {
+        Expression::Op { op, left, right } => {
+            let mut candidates = vec![(**left).clone(), (**right).clone()];
+            let op_with = |left: Expression, right: Expression| Expression::Op {
+                op: *op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+            for left in simpler(left) {
+                candidates.push(op_with(left, (**right).clone()));
+            }
+            for right in simpler(right) {
+                candidates.push(op_with((**left).clone(), right));
+            }
+            candidates
+        }
+        Expression::Neg(operand) => {
+            let mut candidates = vec![(**operand).clone()];
+            let negated = simpler(operand).into_iter().map(Box::new);
+            candidates.extend(negated.map(Expression::Neg));
+            candidates
+        }
+        Expression::Value(0) => vec![],
+        Expression::Value(-1 | 1) => vec![Expression::Value(0)],
+        Expression::Value(v) => vec![Expression::Value(0), Expression::Value(v / 2)],
+        Expression::Var(_) => vec![Expression::Value(0)],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern_matching::dag::Dag;
+    use crate::pattern_matching::{
+        Environment, EvalError, EvalResult, Value, eval, parser::parse, try_eval, try_eval_in,
+        vm::compile,
+    };
+    use std::panic::catch_unwind;
+
+    fn env() -> Environment {
+        let mut env = Environment::new();
+        env.set("x", 7);
+        env.set("y", -3);
+        env
+    }
+
+    fn depth(e: &Expression) -> u32 {
+        match e {
+            Expression::Op { left, right, .. } => 1 + depth(left).max(depth(right)),
+            Expression::Neg(operand) => 1 + depth(operand),
+            _ => 0,
+        }
+    }
+
+    /// Swap the operands of every `+` and `*` in `e`.
+    fn commute(e: &Expression) -> Expression {
+        match e {
+            Expression::Op { op, left, right } => {
+                let (left, right) = (commute(left), commute(right));
+                let (left, right) = match op {
+                    Operation::Add | Operation::Mul => (right, left),
+                    _ => (left, right),
+                };
+                Expression::Op {
+                    op: *op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            }
+            Expression::Neg(operand) => Expression::Neg(Box::new(commute(operand))),
+            _ => e.clone(),
+        }
+    }
+
+    /// Substitute away the `let`s of [`Dag::to_shared_expression`], which
+    /// generated expressions never have.
+    fn inline(e: &Expression) -> Expression {
+        match e {
+            Expression::Let { name, value, body } => substitute(&inline(body), name, value),
+            Expression::Op { op, left, right } => Expression::Op {
+                op: *op,
+                left: Box::new(inline(left)),
+                right: Box::new(inline(right)),
+            },
+            Expression::Neg(operand) => Expression::Neg(Box::new(inline(operand))),
+            _ => e.clone(),
+        }
+    }
+
+    fn substitute(e: &Expression, name: &str, value: &Expression) -> Expression {
+        match e {
+            Expression::Var(n) if n == name => inline(value),
+            Expression::Op { op, left, right } => Expression::Op {
+                op: *op,
+                left: Box::new(substitute(left, name, value)),
+                right: Box::new(substitute(right, name, value)),
+            },
+            Expression::Neg(operand) => Expression::Neg(Box::new(substitute(operand, name, value))),
+            _ => e.clone(),
+        }
+    }
+
+    /// A [`Dag::eval`] result, with the expression an error points at
+    /// written out as a tree.
+    fn unshared(result: EvalResult<Value>) -> EvalResult<Value> {
+        result.map_err(|err| match err {
+            EvalError::DivisionByZero(e) => EvalError::DivisionByZero(inline(&e)),
+            EvalError::Overflow(e) => EvalError::Overflow(inline(&e)),
+            EvalError::NegativeExponent(e) => EvalError::NegativeExponent(inline(&e)),
+            err => err,
+        })
+    }
+
+    #[test]
+    fn test_generator() {
+        let trees = |seed| {
+            let mut generator = Generator::new(seed, Config::default());
+            (0..50).map(|_| generator.expression()).collect::<Vec<_>>()
+        };
+        assert_eq!(trees(3), trees(3));
+        assert_ne!(trees(3), trees(4));
+        assert!(trees(3).iter().all(|e| depth(e) <= 6));
+
+        let config = Config {
+            max_depth: 3,
+            operations: vec![(Operation::Add, 1), (Operation::Sub, 0)],
+            negation: 0,
+            leaf_percent: 0,
+            variables: vec![],
+            ..Config::default()
+        };
+        let mut generator = Generator::new(1, config);
+        let e = generator.expression();
+        assert_eq!(depth(&e), 3);
+        let text = e.to_string();
+        assert!(
+            !text.contains(" - ") && !text.contains(['*', 'x', 'y']),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn test_evaluation_is_deterministic() {
+        let result = find_counterexample(Config::default(), 11, 500, |e| {
+            let value = try_eval_in(e, &env());
+            let (dag, root) = Dag::from_expression(e);
+            value == try_eval_in(e, &env())
+                && value == compile(e).run(&env())
+                && value == unshared(dag.eval(root, &env()))
+        });
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_commutative_operations() {
+        // Evaluation order decides which of two errors is reported, so only
+        // successful results have to match.
+        let result = find_counterexample(Config::default(), 12, 500, |e| {
+            try_eval_in(e, &env()).ok() == try_eval_in(&commute(e), &env()).ok()
+        });
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_eval_never_panics_without_division() {
+        // Small enough that nothing overflows, so `eval` has no error to
+        // panic with.
+        let config = Config {
+            max_depth: 4,
+            operations: vec![
+                (Operation::Add, 2),
+                (Operation::Sub, 2),
+                (Operation::Mul, 1),
+            ],
+            literals: -3..=3,
+            variables: vec![],
+            ..Config::default()
+        };
+        let result = find_counterexample(config, 13, 500, |e| {
+            catch_unwind(|| eval(e.clone())).is_ok()
+        });
+        assert_eq!(result, None);
+
+        // Anything else is reported as an error, never as a panic.
+        let result = find_counterexample(Config::default(), 14, 500, |e| {
+            catch_unwind(|| try_eval(e)).is_ok()
+        });
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_shrinking() {
+        let no_division_by_zero =
+            |e: &Expression| !matches!(try_eval_in(e, &env()), Err(EvalError::DivisionByZero(_)));
+        let counterexample =
+            find_counterexample(Config::default(), 15, 500, no_division_by_zero).unwrap();
+        assert!(
+            ["0 / 0", "0 % 0"].contains(&counterexample.shrunk.to_string().as_str()),
+            "{counterexample}"
+        );
+        assert!(!no_division_by_zero(&counterexample.original));
+
+        let e = parse("(x + 9) * -(y - 100)").unwrap();
+        let shrunk = shrink(
+            &e,
+            |e| matches!(try_eval_in(e, &env()), Ok(v) if v != 0.into()),
+        );
+        assert_eq!(shrunk.to_string(), "x");
+    }
+}
@@ -0,0 +1,538 @@
+//! The numbers an [`Expression`] can evaluate to: checked `i64`s, and a small
+//! numeric tower of integers, exact rationals and IEEE floats.
+//!
+//! Operands of different kinds are promoted before an operation is applied:
+//! an integer meets a rational as a rational, and anything meets a float as a
+//! float. Raising to a non-integer power also promotes to a float, since the
+//! result is generally irrational. Comparisons promote the same way.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::{
+    Comparison, Environment, EvalError, EvalResult, Expression, Operation, Scope, Type, Value,
+    evaluate,
+};
+
+/// An arithmetic failure, before it is tied to the expression that caused it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticError {
+    DivisionByZero,
+    Overflow,
+    NegativeExponent,
+    TypeMismatch { expected: Type, found: Type },
+}
+
+impl ArithmeticError {
+    pub(super) fn at(self, e: &Expression) -> EvalError {
+        let e = e.clone();
+        match self {
+            ArithmeticError::DivisionByZero => EvalError::DivisionByZero(e),
+            ArithmeticError::Overflow => EvalError::Overflow(e),
+            ArithmeticError::NegativeExponent => EvalError::NegativeExponent(e),
+            ArithmeticError::TypeMismatch { expected, found } => EvalError::TypeMismatch {
+                expression: e,
+                expected,
+                found,
+            },
+        }
+    }
+}
+
+/// The numbers an expression evaluates to, and how operations combine them.
+pub trait Arithmetic {
+    type Number: Clone;
+
+    /// Turn a literal or a variable's value into a number.
+    fn literal(&self, v: i64) -> Self::Number;
+
+    /// Apply an arithmetic operation. Comparisons go through
+    /// [`compare`](Self::compare) instead, since they give booleans.
+    fn binary(
+        &self,
+        op: Operation,
+        left: Self::Number,
+        right: Self::Number,
+    ) -> Result<Self::Number, ArithmeticError>;
+
+    fn negate(&self, v: Self::Number) -> Result<Self::Number, ArithmeticError>;
+
+    fn compare(&self, c: Comparison, left: &Self::Number, right: &Self::Number) -> bool;
+}
+
+/// `i64` arithmetic that reports overflow instead of wrapping or panicking.
+pub struct Checked;
+
+impl Arithmetic for Checked {
+    type Number = i64;
+
+    fn literal(&self, v: i64) -> i64 {
+        v
+    }
+
+    fn binary(&self, op: Operation, left: i64, right: i64) -> Result<i64, ArithmeticError> {
+        let result = match op {
+            Operation::Add => left.checked_add(right),
+            Operation::Sub => left.checked_sub(right),
+            Operation::Mul => left.checked_mul(right),
+            Operation::Div | Operation::Mod if right == 0 => {
+                return Err(ArithmeticError::DivisionByZero);
+            }
+            Operation::Div => left.checked_div(right),
+            Operation::Mod => left.checked_rem(right),
+            Operation::Pow => return checked_pow(left, right),
+            Operation::Compare(_) => unreachable!("comparisons go through `compare`"),
+        };
+        result.ok_or(ArithmeticError::Overflow)
+    }
+
+    fn negate(&self, v: i64) -> Result<i64, ArithmeticError> {
+        v.checked_neg().ok_or(ArithmeticError::Overflow)
+    }
+
+    fn compare(&self, c: Comparison, left: &i64, right: &i64) -> bool {
+        c.holds(left.partial_cmp(right))
+    }
+}
+
+fn checked_pow(base: i64, exponent: i64) -> Result<i64, ArithmeticError> {
+    if exponent < 0 {
+        return Err(ArithmeticError::NegativeExponent);
+    }
+    unsigned_pow(base, exponent.unsigned_abs())
+}
+
+/// `base ^ exponent`, for exponents up to `i64::MIN.unsigned_abs()`.
+fn unsigned_pow(base: i64, exponent: u64) -> Result<i64, ArithmeticError> {
+    match u32::try_from(exponent) {
+        Ok(exponent) => base.checked_pow(exponent).ok_or(ArithmeticError::Overflow),
+        // Only these bases stay in range for such huge exponents.
+        Err(_) => match base {
+            0 | 1 => Ok(base),
+            -1 if exponent.is_multiple_of(2) => Ok(1),
+            -1 => Ok(-1),
+            _ => Err(ArithmeticError::Overflow),
+        },
+    }
+}
+
+/// An exact fraction, kept in lowest terms with a positive denominator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rational {
+    numer: i64,
+    denom: i64,
+}
+
+impl Rational {
+    /// The fraction `numer / denom`, or `None` if `denom` is zero or the
+    /// reduced fraction does not fit in `i64`s.
+    pub fn new(numer: i64, denom: i64) -> Option<Self> {
+        Self::reduce(numer.into(), denom.into()).ok()
+    }
+
+    pub fn numer(self) -> i64 {
+        self.numer
+    }
+
+    pub fn denom(self) -> i64 {
+        self.denom
+    }
+
+    fn is_integer(self) -> bool {
+        self.denom == 1
+    }
+
+    fn to_f64(self) -> f64 {
+        self.numer as f64 / self.denom as f64
+    }
+
+    /// Normalize a fraction computed with wide intermediates.
+    fn reduce(numer: i128, denom: i128) -> Result<Self, ArithmeticError> {
+        if denom == 0 {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        let divisor = gcd(numer.unsigned_abs(), denom.unsigned_abs()) as i128;
+        let sign = denom.signum();
+        let fit = |v: i128| i64::try_from(v).map_err(|_| ArithmeticError::Overflow);
+        Ok(Self {
+            numer: fit(sign * numer / divisor)?,
+            denom: fit(sign * denom / divisor)?,
+        })
+    }
+
+    fn binary(op: Operation, a: Self, b: Self) -> Result<Self, ArithmeticError> {
+        let (an, ad, bn, bd) = (
+            a.numer as i128,
+            a.denom as i128,
+            b.numer as i128,
+            b.denom as i128,
+        );
+        match op {
+            Operation::Add => Self::reduce(an * bd + bn * ad, ad * bd),
+            Operation::Sub => Self::reduce(an * bd - bn * ad, ad * bd),
+            Operation::Mul => Self::reduce(an * bn, ad * bd),
+            Operation::Div => Self::reduce(an * bd, ad * bn),
+            // Over the common denominator `ad * bd` the remainder is that of
+            // the numerators, truncated like `i64`'s `%`.
+            Operation::Mod if bn == 0 => Err(ArithmeticError::DivisionByZero),
+            Operation::Mod => Self::reduce((an * bd) % (bn * ad), ad * bd),
+            Operation::Pow => {
+                debug_assert!(b.is_integer(), "non-integer powers are promoted to floats");
+                let exponent = b.numer.unsigned_abs();
+                let numer = unsigned_pow(a.numer, exponent);
+                let denom = unsigned_pow(a.denom, exponent);
+                if b.numer < 0 {
+                    Self::reduce(denom?.into(), numer?.into())
+                } else {
+                    Self::reduce(numer?.into(), denom?.into())
+                }
+            }
+            Operation::Compare(_) => unreachable!("comparisons go through `Number::compare`"),
+        }
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Denominators are positive, so cross-multiplying keeps the order.
+        (self.numer as i128 * other.denom as i128).cmp(&(other.numer as i128 * self.denom as i128))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b > 0 { gcd(b, a % b) } else { a.max(1) }
+}
+
+impl From<i64> for Rational {
+    fn from(v: i64) -> Self {
+        Self { numer: v, denom: 1 }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.numer)
+        } else {
+            write!(f, "{}/{}", self.numer, self.denom)
+        }
+    }
+}
+
+/// A number in the tower `Int` ⊂ `Rational` ⊂ `Float`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Rational(Rational),
+    Float(f64),
+}
+
+impl Number {
+    fn to_rational(self) -> Rational {
+        match self {
+            Number::Int(v) => v.into(),
+            Number::Rational(r) => r,
+            Number::Float(_) => unreachable!("floats are never demoted"),
+        }
+    }
+
+    fn to_f64(self) -> f64 {
+        match self {
+            Number::Int(v) => v as f64,
+            Number::Rational(r) => r.to_f64(),
+            Number::Float(v) => v,
+        }
+    }
+
+    /// Apply `op` after promoting both operands to the wider of their kinds.
+    ///
+    /// Integers divide with truncation and floats follow IEEE 754, so a float
+    /// division by zero gives an infinity or NaN rather than an error.
+    ///
+    /// # Panics
+    ///
+    /// On [`Operation::Compare`], which gives a boolean; use
+    /// [`compare`](Self::compare) for that.
+    pub fn binary(op: Operation, left: Number, right: Number) -> Result<Number, ArithmeticError> {
+        match (left, right) {
+            (Number::Int(l), Number::Int(r)) => Checked.binary(op, l, r).map(Number::Int),
+            (Number::Float(_), _) | (_, Number::Float(_)) => {
+                let (l, r) = (left.to_f64(), right.to_f64());
+                Ok(Number::Float(match op {
+                    Operation::Add => l + r,
+                    Operation::Sub => l - r,
+                    Operation::Mul => l * r,
+                    Operation::Div => l / r,
+                    Operation::Mod => l % r,
+                    Operation::Pow => l.powf(r),
+                    Operation::Compare(_) => unreachable!("comparisons go through `compare`"),
+                }))
+            }
+            _ => {
+                let (l, r) = (left.to_rational(), right.to_rational());
+                if op == Operation::Pow && !r.is_integer() {
+                    return Number::binary(op, Number::Float(l.to_f64()), right);
+                }
+                Rational::binary(op, l, r).map(Number::Rational)
+            }
+        }
+    }
+
+    /// Whether `c` holds between `left` and `right`, promoting them like
+    /// [`binary`](Self::binary) does. A NaN is unordered, so only `!=` holds
+    /// for it.
+    pub fn compare(c: Comparison, left: Number, right: Number) -> bool {
+        let ordering = match (left, right) {
+            (Number::Int(l), Number::Int(r)) => l.partial_cmp(&r),
+            (Number::Float(_), _) | (_, Number::Float(_)) => {
+                left.to_f64().partial_cmp(&right.to_f64())
+            }
+            _ => left.to_rational().partial_cmp(&right.to_rational()),
+        };
+        c.holds(ordering)
+    }
+
+    pub fn negate(self) -> Result<Number, ArithmeticError> {
+        match self {
+            Number::Int(v) => Checked.negate(v).map(Number::Int),
+            Number::Rational(r) => Ok(Number::Rational(Rational {
+                numer: Checked.negate(r.numer)?,
+                denom: r.denom,
+            })),
+            Number::Float(v) => Ok(Number::Float(-v)),
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Int(v) => write!(f, "{v}"),
+            Number::Rational(r) => write!(f, "{r}"),
+            // `Debug` keeps the `.0` on whole floats, e.g. `2.0`.
+            Number::Float(v) => write!(f, "{v:?}"),
+        }
+    }
+}
+
+/// Which kind of [`Number`] literals and variables become, and so how for
+/// example `10 / 3` evaluates: to `3`, `10/3` or `3.3333333333333335`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Integer,
+    Rational,
+    Float,
+}
+
+impl Arithmetic for Mode {
+    type Number = Number;
+
+    fn literal(&self, v: i64) -> Number {
+        match self {
+            Mode::Integer => Number::Int(v),
+            Mode::Rational => Number::Rational(v.into()),
+            Mode::Float => Number::Float(v as f64),
+        }
+    }
+
+    fn binary(
+        &self,
+        op: Operation,
+        left: Number,
+        right: Number,
+    ) -> Result<Number, ArithmeticError> {
+        Number::binary(op, left, right)
+    }
+
+    fn negate(&self, v: Number) -> Result<Number, ArithmeticError> {
+        v.negate()
+    }
+
+    fn compare(&self, c: Comparison, left: &Number, right: &Number) -> bool {
+        Number::compare(c, *left, *right)
+    }
+}
+
+/// Evaluate an expression in the given mode, looking its variables up in `env`.
+pub fn eval_number(e: &Expression, env: &Environment, mode: Mode) -> EvalResult<Value<Number>> {
+    evaluate(e, &Scope::Global(env), 0, &mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern_matching::parser::parse;
+
+    fn eval_in(mode: Mode, input: &str) -> EvalResult<Value<Number>> {
+        eval_number(&parse(input).unwrap(), &Environment::new(), mode)
+    }
+
+    fn ratio(numer: i64, denom: i64) -> Number {
+        Number::Rational(Rational::new(numer, denom).unwrap())
+    }
+
+    #[test]
+    fn test_modes() {
+        assert_eq!(
+            eval_in(Mode::Integer, "10 / 3"),
+            Ok(Value::Number(Number::Int(3)))
+        );
+        assert_eq!(
+            eval_in(Mode::Rational, "10 / 3"),
+            Ok(Value::Number(ratio(10, 3)))
+        );
+        assert_eq!(
+            eval_in(Mode::Float, "10 / 4"),
+            Ok(Value::Number(Number::Float(2.5)))
+        );
+        assert_eq!(
+            eval_in(Mode::Rational, "1/3 + 1/6"),
+            Ok(Value::Number(ratio(1, 2)))
+        );
+        assert_eq!(
+            eval_in(Mode::Rational, "(1/3) * 3"),
+            Ok(Value::Number(ratio(1, 1)))
+        );
+    }
+
+    #[test]
+    fn test_rational() {
+        assert_eq!(Rational::new(6, -4), Rational::new(-3, 2));
+        assert_eq!(Rational::new(1, 0), None);
+        assert_eq!(Rational::new(i64::MIN, -1), None);
+        assert_eq!(ratio(-3, 2).to_string(), "-3/2");
+        assert_eq!(ratio(4, 2).to_string(), "2");
+        assert_eq!(
+            eval_in(Mode::Rational, "(7/2) % (3/2)"),
+            Ok(Value::Number(ratio(1, 2)))
+        );
+        assert_eq!(
+            eval_in(Mode::Rational, "(-7/2) % 2"),
+            Ok(Value::Number(ratio(-3, 2)))
+        );
+        assert_eq!(
+            eval_in(Mode::Rational, "(2/3) ^ -2"),
+            Ok(Value::Number(ratio(9, 4)))
+        );
+        assert_eq!(
+            eval_in(Mode::Rational, "-(1/2)"),
+            Ok(Value::Number(ratio(-1, 2)))
+        );
+    }
+
+    #[test]
+    fn test_promotion() {
+        let half = ratio(1, 2);
+        assert_eq!(
+            Number::binary(Operation::Add, Number::Int(1), half),
+            Ok(ratio(3, 2))
+        );
+        assert_eq!(
+            Number::binary(Operation::Mul, half, Number::Float(3.0)),
+            Ok(Number::Float(1.5))
+        );
+        assert_eq!(
+            eval_in(Mode::Rational, "4 ^ (1/2)"),
+            Ok(Value::Number(Number::Float(2.0)))
+        );
+        assert_eq!(eval_in(Mode::Float, "2 ^ -1").unwrap().to_string(), "0.5");
+    }
+
+    #[test]
+    fn test_comparisons() {
+        assert_eq!(eval_in(Mode::Rational, "1/3 < 1/2"), Ok(Value::Bool(true)));
+        assert_eq!(eval_in(Mode::Integer, "1/3 < 1/2"), Ok(Value::Bool(false)));
+        assert_eq!(
+            eval_in(Mode::Float, "2 / 4 == 1 / 2"),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            eval_in(Mode::Float, "0 / 0 == 0 / 0"),
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            eval_in(Mode::Float, "0 / 0 != 0 / 0"),
+            Ok(Value::Bool(true))
+        );
+        assert!(Number::compare(Comparison::Lt, Number::Int(1), ratio(3, 2)));
+        assert!(Number::compare(
+            Comparison::Ge,
+            ratio(3, 2),
+            Number::Float(1.5)
+        ));
+        assert_eq!(
+            eval_in(Mode::Rational, "if 1/2 > 0 then 1/3 else 2"),
+            Ok(Value::Number(ratio(1, 3)))
+        );
+        assert_eq!(
+            eval_in(Mode::Integer, "if 1/2 > 0 then 1/3 else 2"),
+            Ok(Value::Number(Number::Int(2)))
+        );
+        assert_eq!(
+            eval_in(Mode::Rational, "fn half(x) = x / 2 in half(half(3))"),
+            Ok(Value::Number(ratio(3, 4)))
+        );
+        assert!(matches!(
+            eval_in(Mode::Float, "if 1/2 then 1 else 2"),
+            Err(EvalError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        let e = parse("(1/3) / (1 - 1)").unwrap();
+        assert_eq!(
+            eval_number(&e, &Environment::new(), Mode::Rational),
+            Err(EvalError::DivisionByZero(e.clone()))
+        );
+        let e = parse("(1/9223372036854775807) / 9223372036854775807").unwrap();
+        assert_eq!(
+            eval_number(&e, &Environment::new(), Mode::Rational),
+            Err(EvalError::Overflow(e.clone()))
+        );
+        let e = parse("2 ^ (-9223372036854775807 - 1)").unwrap();
+        assert_eq!(
+            eval_number(&e, &Environment::new(), Mode::Rational),
+            Err(EvalError::Overflow(e.clone()))
+        );
+        assert_eq!(
+            eval_in(Mode::Rational, "(-1) ^ (-9223372036854775807 - 1)"),
+            Ok(Value::Number(ratio(1, 1)))
+        );
+        assert!(matches!(
+            eval_in(Mode::Integer, "2 ^ -1"),
+            Err(EvalError::NegativeExponent(_))
+        ));
+        assert_eq!(
+            eval_in(Mode::Float, "1 / 0"),
+            Ok(Value::Number(Number::Float(f64::INFINITY)))
+        );
+    }
+
+    #[test]
+    fn test_variables() {
+        let e = parse("total / count").unwrap();
+        let mut env = Environment::new();
+        env.set("total", 7);
+        env.set("count", 2);
+        assert_eq!(
+            eval_number(&e, &env, Mode::Integer),
+            Ok(Value::Number(Number::Int(3)))
+        );
+        assert_eq!(
+            eval_number(&e, &env, Mode::Rational),
+            Ok(Value::Number(ratio(7, 2)))
+        );
+        assert_eq!(
+            eval_number(&e, &env, Mode::Float),
+            Ok(Value::Number(Number::Float(3.5)))
+        );
+    }
+}
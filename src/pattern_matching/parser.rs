@@ -0,0 +1,630 @@
+//! Turns infix strings such as `"(3 - 4) * 5 + 10 * 9"` into an [`Expression`].
+
+use std::fmt;
+
+use super::{Comparison, Expression, NEG_PRECEDENCE, Operation};
+
+/// How deeply [`parse`] lets parentheses, negations, powers, arguments and the
+/// parts of `let`, `if` and `fn` nest. The parser recurses once per level,
+/// with frames of several kilobytes in an unoptimized build. Chains like
+/// `1 + 2 + 3` do not nest and can be as long as the input.
+pub const MAX_NESTING: usize = 128;
+
+/// A parse failure, pointing at the byte where the input stopped making sense.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Byte offset of the offending token in the input.
+    pub offset: usize,
+    /// What the parser was looking for at `offset`.
+    pub expected: &'static str,
+    /// What it found there instead.
+    pub found: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected {} at byte {}, found {}",
+            self.expected, self.offset, self.found
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    /// An unsigned integer literal; the sign is handled by the parser.
+    Number(u64),
+    /// A variable or function name; its text is the token's span in the input.
+    Ident,
+    Keyword(Keyword),
+    Op(Operation),
+    /// The `=` of a `let` or `fn`, as opposed to the comparison `==`.
+    Assign,
+    Comma,
+    LeftParen,
+    RightParen,
+    End,
+}
+
+/// Words that cannot be used as names.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Keyword {
+    Let,
+    In,
+    If,
+    Then,
+    Else,
+    Fn,
+    True,
+    False,
+}
+
+/// Splits the input into tokens, remembering where each one starts and ends.
+struct Lexer<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input, pos: 0 }
+    }
+
+    /// Return the next token together with its byte range in the input.
+    fn next_token(&mut self) -> Result<(Token, usize, usize), ParseError> {
+        let rest = &self.input[self.pos..];
+        let start = self.pos + (rest.len() - rest.trim_start().len());
+        let mut chars = self.input[start..].chars();
+
+        let compare = |c| Token::Op(Operation::Compare(c));
+        let (token, len) = match (chars.next(), chars.next()) {
+            (None, _) => (Token::End, 0),
+            (Some('+'), _) => (Token::Op(Operation::Add), 1),
+            (Some('-'), _) => (Token::Op(Operation::Sub), 1),
+            (Some('*'), _) => (Token::Op(Operation::Mul), 1),
+            (Some('/'), _) => (Token::Op(Operation::Div), 1),
+            (Some('%'), _) => (Token::Op(Operation::Mod), 1),
+            (Some('^'), _) => (Token::Op(Operation::Pow), 1),
+            (Some('<'), Some('=')) => (compare(Comparison::Le), 2),
+            (Some('<'), _) => (compare(Comparison::Lt), 1),
+            (Some('>'), Some('=')) => (compare(Comparison::Ge), 2),
+            (Some('>'), _) => (compare(Comparison::Gt), 1),
+            (Some('='), Some('=')) => (compare(Comparison::Eq), 2),
+            (Some('!'), Some('=')) => (compare(Comparison::Ne), 2),
+            (Some('='), _) => (Token::Assign, 1),
+            (Some(','), _) => (Token::Comma, 1),
+            (Some('('), _) => (Token::LeftParen, 1),
+            (Some(')'), _) => (Token::RightParen, 1),
+            (Some(c), _) if c.is_ascii_digit() => {
+                let len = self.input[start..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(self.input.len() - start);
+                let digits = &self.input[start..start + len];
+                let value = digits.parse().map_err(|_| ParseError {
+                    offset: start,
+                    expected: "a 64-bit integer",
+                    found: format!("`{digits}`"),
+                })?;
+                self.pos = start + len;
+                return Ok((Token::Number(value), start, self.pos));
+            }
+            (Some(c), _) if c.is_ascii_alphabetic() || c == '_' || c == '$' => {
+                let len = self.input[start + 1..]
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .map(|len| len + 1)
+                    .unwrap_or(self.input.len() - start);
+                let token = match &self.input[start..start + len] {
+                    "let" => Token::Keyword(Keyword::Let),
+                    "in" => Token::Keyword(Keyword::In),
+                    "if" => Token::Keyword(Keyword::If),
+                    "then" => Token::Keyword(Keyword::Then),
+                    "else" => Token::Keyword(Keyword::Else),
+                    "fn" => Token::Keyword(Keyword::Fn),
+                    "true" => Token::Keyword(Keyword::True),
+                    "false" => Token::Keyword(Keyword::False),
+                    _ => Token::Ident,
+                };
+                (token, len)
+            }
+            (Some(c), _) => {
+                return Err(ParseError {
+                    offset: start,
+                    expected: "a number, name, operator or parenthesis",
+                    found: format!("`{c}`"),
+                });
+            }
+        };
+
+        self.pos = start + len;
+        Ok((token, start, self.pos))
+    }
+}
+
+/// A precedence-climbing parser over the tokens produced by [`Lexer`].
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    token: Token,
+    start: usize,
+    end: usize,
+    /// How many calls of [`expression`](Self::expression) are in progress.
+    nesting: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Result<Self, ParseError> {
+        let mut lexer = Lexer::new(input);
+        let (token, start, end) = lexer.next_token()?;
+        Ok(Self {
+            lexer,
+            token,
+            start,
+            end,
+            nesting: 0,
+        })
+    }
+
+    fn advance(&mut self) -> Result<(), ParseError> {
+        (self.token, self.start, self.end) = self.lexer.next_token()?;
+        Ok(())
+    }
+
+    /// Build an error saying the current token is not what we `expected`.
+    fn error(&self, expected: &'static str) -> ParseError {
+        self.error_at(self.start, self.end, expected)
+    }
+
+    /// Skip over `token`, or fail saying it was `expected`.
+    fn expect(&mut self, token: Token, expected: &'static str) -> Result<(), ParseError> {
+        if self.token != token {
+            return Err(self.error(expected));
+        }
+        self.advance()
+    }
+
+    fn error_at(&self, start: usize, end: usize, expected: &'static str) -> ParseError {
+        // Only the end of input has an empty span.
+        let found = if start == end {
+            String::from("end of input")
+        } else {
+            format!("`{}`", &self.lexer.input[start..end])
+        };
+        ParseError {
+            offset: start,
+            expected,
+            found,
+        }
+    }
+
+    /// Parse an expression whose operations bind at least as tightly as
+    /// `min_precedence`.
+    fn expression(&mut self, min_precedence: u8) -> Result<Expression, ParseError> {
+        if self.nesting == MAX_NESTING {
+            return Err(self.error("a less deeply nested expression"));
+        }
+        self.nesting += 1;
+        let result = (self.unary()).and_then(|left| self.operations(left, min_precedence));
+        self.nesting -= 1;
+        result
+    }
+
+    /// Extend `left` with the operations that follow it, as long as they bind
+    /// at least as tightly as `min_precedence`.
+    ///
+    /// Chains of left-associative operations are folded in a loop, so long
+    /// inputs like `1 + 1 + ... + 1` do not recurse once per operator.
+    fn operations(
+        &mut self,
+        mut left: Expression,
+        min_precedence: u8,
+    ) -> Result<Expression, ParseError> {
+        while let Token::Op(op) = self.token {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.advance()?;
+            let right = match op.is_right_associative() {
+                true => self.expression(op.precedence())?,
+                false => self.expression(op.precedence() + 1)?,
+            };
+            left = Expression::Op {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    /// Parse a negation or a primary expression.
+    fn unary(&mut self) -> Result<Expression, ParseError> {
+        if self.token != Token::Op(Operation::Sub) {
+            return self.primary();
+        }
+        self.advance()?;
+
+        // `-3` is a negative literal, but `-3 ^ 2` negates `3 ^ 2`.
+        if let Token::Number(n) = self.token {
+            let (start, end) = (self.start, self.end);
+            self.advance()?;
+            if self.token != Token::Op(Operation::Pow) {
+                return 0_i64
+                    .checked_sub_unsigned(n)
+                    .map(Expression::Value)
+                    .ok_or_else(|| self.error_at(start, end, "a 64-bit integer"));
+            }
+            let base =
+                i64::try_from(n).map_err(|_| self.error_at(start, end, "a 64-bit integer"))?;
+            let operand = self.operations(Expression::Value(base), NEG_PRECEDENCE)?;
+            return Ok(Expression::Neg(Box::new(operand)));
+        }
+
+        let operand = self.expression(NEG_PRECEDENCE)?;
+        Ok(Expression::Neg(Box::new(operand)))
+    }
+
+    /// Parse a literal, a variable, a call, a parenthesized expression or one
+    /// of the forms introduced by a keyword.
+    fn primary(&mut self) -> Result<Expression, ParseError> {
+        match self.token {
+            Token::Number(n) => {
+                let value = i64::try_from(n).map_err(|_| self.error("a 64-bit integer"))?;
+                self.advance()?;
+                Ok(Expression::Value(value))
+            }
+            Token::Keyword(keyword @ (Keyword::True | Keyword::False)) => {
+                self.advance()?;
+                Ok(Expression::Bool(keyword == Keyword::True))
+            }
+            Token::Ident => {
+                let name = self.name()?;
+                if self.token != Token::LeftParen {
+                    return Ok(Expression::Var(name));
+                }
+                let args = self.list(|parser| parser.expression(0))?;
+                Ok(Expression::Call { name, args })
+            }
+            Token::Keyword(Keyword::Let) => {
+                self.advance()?;
+                let name = self.name()?;
+                self.expect(Token::Assign, "`=`")?;
+                let value = self.expression(0)?;
+                self.expect(Token::Keyword(Keyword::In), "`in`")?;
+                let body = self.expression(0)?;
+                Ok(Expression::Let {
+                    name,
+                    value: Box::new(value),
+                    body: Box::new(body),
+                })
+            }
+            Token::Keyword(Keyword::If) => {
+                self.advance()?;
+                let condition = self.expression(0)?;
+                self.expect(Token::Keyword(Keyword::Then), "`then`")?;
+                let consequent = self.expression(0)?;
+                self.expect(Token::Keyword(Keyword::Else), "`else`")?;
+                let alternative = self.expression(0)?;
+                Ok(Expression::If {
+                    condition: Box::new(condition),
+                    consequent: Box::new(consequent),
+                    alternative: Box::new(alternative),
+                })
+            }
+            Token::Keyword(Keyword::Fn) => {
+                self.advance()?;
+                let name = self.name()?;
+                let params = self.list(Self::name)?;
+                self.expect(Token::Assign, "`=`")?;
+                let body = self.expression(0)?;
+                self.expect(Token::Keyword(Keyword::In), "`in`")?;
+                let rest = self.expression(0)?;
+                Ok(Expression::Function {
+                    name,
+                    params,
+                    body: Box::new(body),
+                    rest: Box::new(rest),
+                })
+            }
+            Token::LeftParen => {
+                self.advance()?;
+                let inner = self.expression(0)?;
+                if self.token != Token::RightParen {
+                    return Err(self.error("`)`"));
+                }
+                self.advance()?;
+                Ok(inner)
+            }
+            _ => Err(self.error("a number, name or `(`")),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        if self.token != Token::Ident {
+            return Err(self.error("a name"));
+        }
+        let name = self.lexer.input[self.start..self.end].to_string();
+        self.advance()?;
+        Ok(name)
+    }
+
+    /// Parse a parenthesized, comma separated list of `item`s, such as the
+    /// arguments of a call.
+    fn list<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Vec<T>, ParseError> {
+        self.expect(Token::LeftParen, "`(`")?;
+        let mut items = Vec::new();
+        if self.token == Token::RightParen {
+            self.advance()?;
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            match self.token {
+                Token::Comma => self.advance()?,
+                Token::RightParen => break self.advance().map(|()| items),
+                _ => return Err(self.error("`,` or `)`")),
+            }
+        }
+    }
+}
+
+/// Parse an infix expression.
+///
+/// From loosest to tightest: the comparisons `<`, `<=`, `>`, `>=`, `==` and
+/// `!=`; `+` and `-`; `*`, `/` and `%`; prefix `-`; and `^`. All operations
+/// associate to the left except `^`, so `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`, and
+/// parentheses group as usual. A `-` directly in front of a number makes a
+/// negative literal rather than a negation. Names made of letters, digits and `_`
+/// (not starting with a digit) are variables; they may also start with `$`,
+/// as in the `$1` the calculator binary gives its results. `true` and
+/// `false` are the boolean literals.
+///
+/// `let x = 1 in x + 1`, `if x < 0 then -x else x` and
+/// `fn sq(x) = x * x in sq(3) + sq(4)` extend as far to the right as they can.
+/// Their keywords cannot be used as names, and a name directly followed by
+/// `(` is a call.
+///
+/// Input nested deeper than [`MAX_NESTING`] is rejected with an error rather
+/// than overflowing the stack.
+pub fn parse(input: &str) -> Result<Expression, ParseError> {
+    let mut parser = Parser::new(input)?;
+    let expression = parser.expression(0)?;
+    if parser.token != Token::End {
+        return Err(parser.error("an operator or end of input"));
+    }
+    Ok(expression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern_matching::typecheck::check;
+    use crate::pattern_matching::vm::compile;
+    use crate::pattern_matching::{Environment, Type, Value, eval, try_eval, try_eval_in};
+
+    fn op(op: Operation, left: Expression, right: Expression) -> Expression {
+        Expression::Op {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(
+            parse("1 + 2 * 3").unwrap(),
+            op(
+                Operation::Add,
+                Expression::Value(1),
+                op(Operation::Mul, Expression::Value(2), Expression::Value(3)),
+            )
+        );
+        assert_eq!(eval(parse("(3 - 4) * 5 + 10 * 9").unwrap()), 85);
+    }
+
+    #[test]
+    fn test_left_associative() {
+        assert_eq!(
+            parse("8 - 4 - 2").unwrap(),
+            op(
+                Operation::Sub,
+                op(Operation::Sub, Expression::Value(8), Expression::Value(4)),
+                Expression::Value(2),
+            )
+        );
+        assert_eq!(eval(parse("100 / 10 / 5").unwrap()), 2);
+        assert_eq!(eval(parse("2 * 3 / 4").unwrap()), 1);
+    }
+
+    #[test]
+    fn test_parentheses() {
+        assert_eq!(eval(parse("8 - (4 - 2)").unwrap()), 6);
+        assert_eq!(eval(parse("((((7))))").unwrap()), 7);
+        assert_eq!(eval(parse("2*(3+4)").unwrap()), 14);
+    }
+
+    #[test]
+    fn test_negative_literals() {
+        assert_eq!(parse("-5").unwrap(), Expression::Value(-5));
+        assert_eq!(eval(parse("3 - -4").unwrap()), 7);
+        assert_eq!(
+            parse("-9223372036854775808").unwrap(),
+            Expression::Value(i64::MIN)
+        );
+        let err = parse("-9223372036854775808 ^ 2").unwrap_err();
+        assert_eq!((err.offset, err.expected), (1, "a 64-bit integer"));
+    }
+
+    #[test]
+    fn test_mod_pow_neg() {
+        assert_eq!(eval(parse("2 ^ 3 ^ 2").unwrap()), 512);
+        assert_eq!(eval(parse("(2 ^ 3) ^ 2").unwrap()), 64);
+        assert_eq!(eval(parse("-2 ^ 2").unwrap()), -4);
+        assert_eq!(eval(parse("(-2) ^ 2").unwrap()), 4);
+        assert_eq!(eval(parse("2 * 3 ^ 2 % 5").unwrap()), 3);
+        assert_eq!(eval(parse("-(1 + 2) * 3").unwrap()), -9);
+        assert_eq!(eval(parse("--3").unwrap()), 3);
+        assert_eq!(
+            parse("-x").unwrap(),
+            Expression::Neg(Box::new(Expression::Var(String::from("x"))))
+        );
+        assert_eq!(
+            parse("-(3)").unwrap(),
+            Expression::Neg(Box::new(Expression::Value(3)))
+        );
+        assert_eq!(
+            parse("2 ^ -1").unwrap(),
+            op(Operation::Pow, Expression::Value(2), Expression::Value(-1))
+        );
+    }
+
+    #[test]
+    fn test_variables() {
+        let e = parse("rate * hours_2 + _bonus - $1").unwrap();
+        let mut env = Environment::new();
+        env.set("rate", 20);
+        env.set("hours_2", 8);
+        env.set("_bonus", 5);
+        env.set("$1", 65);
+        assert_eq!(try_eval_in(&e, &env), Ok(Value::Number(100)));
+    }
+
+    #[test]
+    fn test_let_if_fn() {
+        let var = |name: &str| Expression::Var(String::from(name));
+        assert_eq!(
+            parse("let x = 1 in x").unwrap(),
+            Expression::Let {
+                name: String::from("x"),
+                value: Box::new(Expression::Value(1)),
+                body: Box::new(var("x")),
+            }
+        );
+        assert_eq!(
+            parse("f() + g(x, 2)").unwrap(),
+            op(
+                Operation::Add,
+                Expression::Call {
+                    name: String::from("f"),
+                    args: vec![],
+                },
+                Expression::Call {
+                    name: String::from("g"),
+                    args: vec![var("x"), Expression::Value(2)],
+                },
+            )
+        );
+        assert_eq!(
+            parse("if a then b else c").unwrap(),
+            Expression::If {
+                condition: Box::new(var("a")),
+                consequent: Box::new(var("b")),
+                alternative: Box::new(var("c")),
+            }
+        );
+        assert_eq!(
+            parse("1 < 2 + 3").unwrap(),
+            op(
+                Operation::Compare(Comparison::Lt),
+                Expression::Value(1),
+                op(Operation::Add, Expression::Value(2), Expression::Value(3)),
+            )
+        );
+        assert_eq!(eval(parse("1 + let x = 2 in x * 3").unwrap()), 7);
+        assert_eq!(eval(parse("(if 0 > 1 then 1 else 2) * 3").unwrap()), 6);
+        assert_eq!(
+            eval(parse("fn sq(x) = x * x in sq(3) + sq(4)").unwrap()),
+            25
+        );
+        assert_eq!(parse("true").unwrap(), Expression::Bool(true));
+        assert_eq!(
+            parse("x == false").unwrap(),
+            op(
+                Operation::Compare(Comparison::Eq),
+                var("x"),
+                Expression::Bool(false),
+            )
+        );
+        let run = |input: &str| try_eval(&parse(input).unwrap());
+        assert_eq!(run("1 <= 1 == true != false"), Ok(Value::Bool(true)));
+        assert_eq!(run("2 == 2 != true"), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = parse("1 + * 2").unwrap_err();
+        assert_eq!(err.offset, 4);
+        assert_eq!(err.expected, "a number, name or `(`");
+        assert_eq!(err.found, "`*`");
+
+        let err = parse("(1 + 2").unwrap_err();
+        assert_eq!((err.offset, err.expected), (6, "`)`"));
+        assert_eq!(err.found, "end of input");
+
+        let err = parse("1 2").unwrap_err();
+        assert_eq!(
+            (err.offset, err.expected),
+            (2, "an operator or end of input")
+        );
+
+        let err = parse("1 + #").unwrap_err();
+        assert_eq!(err.offset, 4);
+        assert_eq!(err.found, "`#`");
+
+        let err = parse("9223372036854775808").unwrap_err();
+        assert_eq!((err.offset, err.expected), (0, "a 64-bit integer"));
+
+        let err = parse("let 1 = 2 in 3").unwrap_err();
+        assert_eq!((err.offset, err.expected), (4, "a name"));
+        let err = parse("let x == 2 in x").unwrap_err();
+        assert_eq!((err.offset, err.expected), (6, "`=`"));
+        let err = parse("let x = 2 x").unwrap_err();
+        assert_eq!((err.offset, err.expected), (10, "`in`"));
+        let err = parse("if x then y").unwrap_err();
+        assert_eq!((err.offset, err.found.as_str()), (11, "end of input"));
+        let err = parse("fn f(x y) = x in f(1)").unwrap_err();
+        assert_eq!((err.offset, err.expected), (7, "`,` or `)`"));
+        let err = parse("fn (x) = x in 1").unwrap_err();
+        assert_eq!((err.offset, err.expected), (3, "a name"));
+        let err = parse("f(1,)").unwrap_err();
+        assert_eq!(err.offset, 4);
+        let err = parse("let true = 1 in 2").unwrap_err();
+        assert_eq!((err.offset, err.expected), (4, "a name"));
+        let err = parse("x ! y").unwrap_err();
+        assert_eq!((err.offset, err.found.as_str()), (2, "`!`"));
+
+        assert_eq!(
+            parse("").unwrap_err().to_string(),
+            "expected a number, name or `(` at byte 0, found end of input"
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        // Chains are folded in a loop, however long, and the tree they make
+        // can be checked, printed, cloned, dropped and run on the stack machine.
+        let input = format!("{}1", "1 + ".repeat(199_999));
+        let sum = parse(&input).unwrap();
+        assert_eq!(check(&sum, &Environment::new()), Ok(Type::Number));
+        assert_eq!(sum.to_string(), input);
+        assert_eq!(
+            compile(&sum.clone()).run(&Environment::new()),
+            Ok(Value::Number(200_000))
+        );
+
+        let nested = |levels: usize| format!("{}1{}", "(".repeat(levels), ")".repeat(levels));
+        assert_eq!(parse(&nested(MAX_NESTING - 1)), Ok(Expression::Value(1)));
+        let err = parse(&nested(50_000)).unwrap_err();
+        assert_eq!(
+            (err.offset, err.expected),
+            (MAX_NESTING, "a less deeply nested expression")
+        );
+        assert!(parse(&format!("{}x", "-(".repeat(MAX_NESTING))).is_err());
+        assert!(parse(&format!("{}1", "f(".repeat(MAX_NESTING))).is_err());
+    }
+}
@@ -0,0 +1,308 @@
+//! Rewrites an [`Expression`] into a smaller one that evaluates the same way
+//! under [`try_eval_in`](super::try_eval_in). Constants are folded with `i64`
+//! arithmetic, so the result is not meant for the rational or float modes.
+//!
+//! Rewrites never hide an error: a constant subtree that fails to evaluate is
+//! left in place, and identities that drop an operand (`x * 0`, `x - x`) only
+//! fire when that operand is a literal or a variable bound by an enclosing
+//! `let` or parameter. Identities that keep an operand (`x + 0`, `x * 1`) only
+//! fire when it is known to give a number, so `true + 0` still fails.
+
+use super::number::Checked;
+use super::{Expression, Operation, operate};
+
+/// The variables bound by the `let`s and parameters around a subexpression,
+/// innermost last, each with whether it is known to hold a number.
+type Bound<'a> = Vec<(&'a str, bool)>;
+
+/// Fold constant subtrees, apply simple algebraic identities and put the
+/// operands of `+` and `*` into a canonical order. An `if` whose condition
+/// folds to a constant is replaced by the branch it would take.
+pub fn simplify(e: &Expression) -> Expression {
+    simplify_in(e, &mut Vec::new())
+}
+
+fn simplify_in<'a>(e: &'a Expression, bound: &mut Bound<'a>) -> Expression {
+    match e {
+        Expression::Op { op, left, right } => {
+            let (left, right) = (simplify_in(left, bound), simplify_in(right, bound));
+            simplify_op(*op, left, right, bound)
+        }
+        Expression::Neg(operand) => match simplify_in(operand, bound) {
+            Expression::Value(v) if v != i64::MIN => Expression::Value(-v),
+            operand => Expression::Neg(Box::new(operand)),
+        },
+        Expression::Value(_) | Expression::Bool(_) | Expression::Var(_) => e.clone(),
+        Expression::Let { name, value, body } => {
+            let value = simplify_in(value, bound);
+            bound.push((name, is_number(&value, bound)));
+            let body = simplify_in(body, bound);
+            bound.pop();
+            Expression::Let {
+                name: name.clone(),
+                value: Box::new(value),
+                body: Box::new(body),
+            }
+        }
+        Expression::If {
+            condition,
+            consequent,
+            alternative,
+        } => match simplify_in(condition, bound) {
+            Expression::Bool(true) => simplify_in(consequent, bound),
+            Expression::Bool(false) => simplify_in(alternative, bound),
+            condition => Expression::If {
+                condition: Box::new(condition),
+                consequent: Box::new(simplify_in(consequent, bound)),
+                alternative: Box::new(simplify_in(alternative, bound)),
+            },
+        },
+        Expression::Function {
+            name,
+            params,
+            body,
+            rest,
+        } => {
+            let outer = bound.len();
+            bound.extend(params.iter().map(|p| (p.as_str(), false)));
+            let body = simplify_in(body, bound);
+            bound.truncate(outer);
+            Expression::Function {
+                name: name.clone(),
+                params: params.clone(),
+                body: Box::new(body),
+                rest: Box::new(simplify_in(rest, bound)),
+            }
+        }
+        Expression::Call { name, args } => Expression::Call {
+            name: name.clone(),
+            args: args.iter().map(|arg| simplify_in(arg, bound)).collect(),
+        },
+    }
+}
+
+/// Simplify an operation whose operands simplified to `left` and `right`.
+fn simplify_op(op: Operation, left: Expression, right: Expression, bound: &Bound) -> Expression {
+    if let (Some(l), Some(r)) = (left.literal(), right.literal())
+        && let Ok(v) = operate(&Checked, op, l, r)
+    {
+        return v.into();
+    }
+
+    let number = |x| is_number(x, bound);
+    let droppable = |x| cannot_fail(x, bound) && is_number(x, bound);
+    match (op, &left, &right) {
+        (Operation::Add, x, Expression::Value(0))
+        | (Operation::Add, Expression::Value(0), x)
+        | (Operation::Sub, x, Expression::Value(0))
+        | (Operation::Mul, x, Expression::Value(1))
+        | (Operation::Mul, Expression::Value(1), x)
+        | (Operation::Div, x, Expression::Value(1))
+        | (Operation::Pow, x, Expression::Value(1))
+            if number(x) =>
+        {
+            return x.clone();
+        }
+        (Operation::Mul, x, Expression::Value(0))
+        | (Operation::Mul, Expression::Value(0), x)
+        | (Operation::Mod, x, Expression::Value(1))
+            if droppable(x) =>
+        {
+            return Expression::Value(0);
+        }
+        (Operation::Pow, x, Expression::Value(0)) if droppable(x) => {
+            return Expression::Value(1);
+        }
+        (Operation::Sub, x, y) if x == y && droppable(x) => return Expression::Value(0),
+        _ => {}
+    }
+
+    // Swapping operands changes which one is evaluated first, so only do it
+    // when one of them cannot fail and the reported error stays the same.
+    let swap = matches!(op, Operation::Add | Operation::Mul)
+        && (cannot_fail(&left, bound) || cannot_fail(&right, bound))
+        && rank(&right) < rank(&left);
+    let (left, right) = if swap { (right, left) } else { (left, right) };
+    Expression::Op {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+/// What is known about a variable: `None` if it is free, and so may be
+/// unbound, otherwise whether it holds a number.
+fn lookup(name: &str, bound: &Bound) -> Option<bool> {
+    bound
+        .iter()
+        .rev()
+        .find(|(n, _)| *n == name)
+        .map(|&(_, number)| number)
+}
+
+/// Literals and variables bound by a `let` or parameter evaluate without error.
+fn cannot_fail(e: &Expression, bound: &Bound) -> bool {
+    match e {
+        Expression::Value(_) | Expression::Bool(_) => true,
+        Expression::Var(name) => lookup(name, bound).is_some(),
+        _ => false,
+    }
+}
+
+/// Whether `e` gives a number whenever it evaluates without error.
+fn is_number(e: &Expression, bound: &Bound) -> bool {
+    match e {
+        Expression::Value(_) | Expression::Neg(_) => true,
+        Expression::Op { op, .. } => !matches!(op, Operation::Compare(_)),
+        Expression::Var(name) => lookup(name, bound) == Some(true),
+        Expression::If {
+            consequent,
+            alternative,
+            ..
+        } => is_number(consequent, bound) && is_number(alternative, bound),
+        _ => false,
+    }
+}
+
+/// Sort key for operands of commutative operations: literals first, then
+/// variables by name, then everything else.
+fn rank(e: &Expression) -> (u8, i64, &str) {
+    match e {
+        Expression::Value(v) => (0, *v, ""),
+        Expression::Var(name) => (1, 0, name),
+        _ => (2, 0, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern_matching::parser::parse;
+    use crate::pattern_matching::{Environment, EvalError, try_eval, try_eval_in};
+    use std::mem::discriminant;
+
+    fn check(input: &str, expected: &str) {
+        assert_eq!(
+            simplify(&parse(input).unwrap()),
+            parse(expected).unwrap(),
+            "simplifying {input}"
+        );
+    }
+
+    /// Like `check`, with `x`, `y` and `z` bound to numbers.
+    fn check_numbers(input: &str, expected: &str) {
+        let bind = "let x = -a in let y = -b in let z = -c in ";
+        check(&format!("{bind}{input}"), &format!("{bind}{expected}"));
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        check("(3 - 4) * 5 + 10 * 9", "85");
+        check("x + 2 * 3", "6 + x");
+        check_numbers("x * (8 / 4 - 1)", "x");
+    }
+
+    #[test]
+    fn test_identities() {
+        check_numbers("x * 1", "x");
+        check_numbers("1 * x", "x");
+        check_numbers("x + 0", "x");
+        check_numbers("0 + x", "x");
+        check_numbers("x - 0", "x");
+        check_numbers("x / 1", "x");
+        check_numbers("x * 0", "0");
+        check_numbers("0 * x", "0");
+        check_numbers("x - x", "0");
+        check_numbers("x % 1", "0");
+        check_numbers("x ^ 1", "x");
+        check_numbers("x ^ 0", "1");
+        check_numbers("-(2 * 3) + x", "-6 + x");
+        check_numbers("-(x * 1)", "-x");
+        check_numbers("(x - x) * y + z", "z");
+        check_numbers("(x * y) * (z - z)", "0 * (x * y)");
+    }
+
+    #[test]
+    fn test_let_if_fn() {
+        check("let x = 2 * 3 in x * 1", "let x = 6 in x");
+        check("if 1 < 2 then x else 1 / 0", "x");
+        check("if 2 == 3 then 1 / 0 else -y + 0", "-y");
+        check("if true then x else y", "x");
+        check(
+            "if x > 0 then 1 + 1 else 2 * y",
+            "if x > 0 then 2 else 2 * y",
+        );
+        check("2 < 1 == (1 == 1)", "false");
+        check("fn f(a) = -a * 1 in f(0 + 3)", "fn f(a) = -a in f(3)");
+        check("fn f(a) = a * 1 in f(true)", "fn f(a) = 1 * a in f(true)");
+    }
+
+    #[test]
+    fn test_commutative_order() {
+        check("x + 3", "3 + x");
+        check("y * x", "y * x");
+        check_numbers("y * x", "x * y");
+        check("(a - b) * 2", "2 * (a - b)");
+        check("x - 3", "x - 3");
+        check("(a - b) + (c - d)", "(a - b) + (c - d)");
+    }
+
+    #[test]
+    fn test_keeps_errors() {
+        check("1 / 0", "1 / 0");
+        check("(1 / 0) * 0", "0 * (1 / 0)");
+        check("x + 9223372036854775807 * 2", "x + 2 * 9223372036854775807");
+        check("(1 / 0) - (1 / 0)", "(1 / 0) - (1 / 0)");
+        check("(1 % 0) ^ 0", "(1 % 0) ^ 0");
+        check("2 ^ -1", "2 ^ -1");
+        check("-(-9223372036854775808)", "-(-9223372036854775808)");
+    }
+
+    #[test]
+    fn test_keeps_unbound_and_type_errors() {
+        for input in ["x * 0", "x - x"] {
+            assert_eq!(
+                try_eval(&simplify(&parse(input).unwrap())),
+                Err(EvalError::UnboundVariable("x".into())),
+                "{input}"
+            );
+        }
+        for input in ["true + 0", "(1 < 2) * 1"] {
+            assert!(
+                matches!(
+                    try_eval(&simplify(&parse(input).unwrap())),
+                    Err(EvalError::TypeMismatch { .. })
+                ),
+                "{input}"
+            );
+        }
+        check("let b = true in b - b", "let b = true in b - b");
+    }
+
+    #[test]
+    fn test_same_result() {
+        let mut env = Environment::new();
+        env.set("x", 6);
+        env.set("y", -2);
+        for input in [
+            "x * (y - y) + 7 / (x - 6)",
+            "(x + 0) * (1 * y) - (3 - 3) * x",
+            "y / (x + y - 4) * 0",
+            "9223372036854775807 + x * 1 - x",
+            "(x * 2 + y * 3) / (1 + 1) - x / y",
+            "0 - x - 0 - y * 0",
+            "-(x % 4) ^ 2 + y ^ (x - 6)",
+            "(x - 6) ^ -1 * 0",
+            "let z = x - x in if z == 0 then y * 1 else 1 / z",
+            "fn f(n) = if n > 0 then n * f(n - 1) else 1 in f(x) - f(x - 1) * x",
+        ] {
+            let e = parse(input).unwrap();
+            let (before, after) = (try_eval_in(&e, &env), try_eval_in(&simplify(&e), &env));
+            match (&before, &after) {
+                (Ok(_), _) => assert_eq!(before, after, "{input}"),
+                (Err(b), Err(a)) => assert_eq!(discriminant(b), discriminant(a), "{input}"),
+                (Err(_), Ok(_)) => panic!("simplifying {input} removed {before:?}"),
+            }
+        }
+    }
+}
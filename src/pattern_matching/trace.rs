@@ -0,0 +1,337 @@
+//! Step-by-step evaluation of an [`Expression`], for seeing how it reduces.
+//!
+//! Each step replaces the leftmost sub-expression whose operands are already
+//! values with its value, so `(3 - 4) * 5` reduces to `-1 * 5` and then to
+//! `-5`. A `let` whose value is known is substituted into its body, an `if`
+//! whose condition is known becomes the branch it takes, and a call whose
+//! arguments are known is evaluated in a single step. Every step is sent to a
+//! [`Logger`], which makes the trace easy to
+//! narrow down with [`VerbosityFilter`](crate::generic_functions::VerbosityFilter)
+//! or [`Filter`](crate::closures::Filter).
+
+use super::number::Checked;
+use super::{Environment, EvalError, EvalResult, Expression, Scope, Value, call, negate, operate};
+use crate::generic_functions::Logger;
+
+/// Verbosity of the whole expression after each step, starting with the
+/// expression as given.
+pub const STEP: u8 = 1;
+
+/// Verbosity of the single reduction each step makes, e.g. `3 - 4 => -1`.
+pub const REDUCTION: u8 = 2;
+
+/// Evaluate an expression like [`try_eval_in`](super::try_eval_in), logging
+/// every intermediate expression at [`STEP`] and every reduction at
+/// [`REDUCTION`] verbosity.
+///
+/// An error names the failing sub-expression as it reads at that point of the
+/// trace, with its operands already reduced to values.
+pub fn trace<L: Logger>(e: &Expression, env: &Environment, logger: &L) -> EvalResult<Value> {
+    let mut e = e.clone();
+    logger.log(STEP, &e.to_string());
+    loop {
+        if let Some(v) = e.literal() {
+            return Ok(v);
+        }
+        step(&mut e, &Scope::Global(env), logger)?;
+        logger.log(STEP, &e.to_string());
+    }
+}
+
+/// Reduce the leftmost sub-expression of `e` whose operands are values.
+/// `scope` holds the functions defined around `e`.
+fn step<L: Logger>(e: &mut Expression, scope: &Scope<i64>, logger: &L) -> EvalResult<()> {
+    if let Expression::Call { .. } = e {
+        // Like `try_eval_in`, look the function up before the arguments.
+        scope.function(e)?;
+    }
+    match e {
+        Expression::Op { left, .. } if !is_value(left) => return step(left, scope, logger),
+        Expression::Op { right, .. }
+        | Expression::Neg(right)
+        | Expression::Let { value: right, .. }
+        | Expression::If {
+            condition: right, ..
+        } if !is_value(right) => {
+            return step(right, scope, logger);
+        }
+        Expression::Call { args, .. } if !args.iter().all(is_value) => {
+            let arg = args.iter_mut().find(|arg| !is_value(arg));
+            return step(arg.expect("some argument is not a value"), scope, logger);
+        }
+        Expression::Function {
+            name,
+            params,
+            body,
+            rest,
+        } if !is_value(rest) => {
+            let scope = Scope::Function {
+                name,
+                params,
+                body,
+                parent: scope,
+            };
+            return step(rest, &scope, logger);
+        }
+        Expression::Let { name, value, body } => {
+            let mut reduced = (**body).clone();
+            substitute(&mut reduced, name, value_of(value));
+            return replace(e, reduced, logger);
+        }
+        Expression::If {
+            condition,
+            consequent,
+            alternative,
+        } => {
+            let taken = value_of(condition).boolean();
+            let taken = if taken.map_err(|err| err.at(condition))? {
+                consequent
+            } else {
+                alternative
+            };
+            let reduced = (**taken).clone();
+            return replace(e, reduced, logger);
+        }
+        Expression::Function { rest, .. } => {
+            let reduced = (**rest).clone();
+            return replace(e, reduced, logger);
+        }
+        _ => {}
+    }
+
+    let value = match &*e {
+        Expression::Op { op, left, right } => {
+            operate(&Checked, *op, value_of(left), value_of(right)).map_err(|err| err.at(e))?
+        }
+        Expression::Neg(operand) => negate(&Checked, value_of(operand)).map_err(|err| err.at(e))?,
+        Expression::Var(name) => scope
+            .var(name, &Checked)
+            .ok_or_else(|| EvalError::UnboundVariable(name.clone()))?,
+        Expression::Call { args, .. } => {
+            let args = args.iter().map(value_of).collect();
+            call(e, scope.function(e)?, args, 0, &Checked)?
+        }
+        _ => unreachable!("all other expressions are reduced above"),
+    };
+    logger.log(REDUCTION, &format!("{e} => {value}"));
+    *e = value.into();
+    Ok(())
+}
+
+/// Replace `e` with what it reduced to.
+fn replace<L: Logger>(e: &mut Expression, reduced: Expression, logger: &L) -> EvalResult<()> {
+    logger.log(REDUCTION, &format!("{e} => {reduced}"));
+    *e = reduced;
+    Ok(())
+}
+
+/// Replace the free occurrences of the variable `name` in `e` with `value`.
+fn substitute(e: &mut Expression, name: &str, value: Value) {
+    match e {
+        Expression::Var(n) if n == name => *e = value.into(),
+        Expression::Value(_) | Expression::Bool(_) | Expression::Var(_) => {}
+        Expression::Op { left, right, .. } => {
+            substitute(left, name, value);
+            substitute(right, name, value);
+        }
+        Expression::Neg(operand) => substitute(operand, name, value),
+        Expression::Let {
+            name: bound,
+            value: bound_value,
+            body,
+        } => {
+            substitute(bound_value, name, value);
+            if bound != name {
+                substitute(body, name, value);
+            }
+        }
+        Expression::If {
+            condition,
+            consequent,
+            alternative,
+        } => {
+            substitute(condition, name, value);
+            substitute(consequent, name, value);
+            substitute(alternative, name, value);
+        }
+        Expression::Function {
+            params, body, rest, ..
+        } => {
+            if !params.iter().any(|p| p == name) {
+                substitute(body, name, value);
+            }
+            substitute(rest, name, value);
+        }
+        Expression::Call { args, .. } => {
+            for arg in args {
+                substitute(arg, name, value);
+            }
+        }
+    }
+}
+
+fn is_value(e: &Expression) -> bool {
+    e.literal().is_some()
+}
+
+fn value_of(e: &Expression) -> Value {
+    e.literal().expect("only called once operands are values")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::closures::Filter;
+    use crate::generic_functions::VerbosityFilter;
+    use crate::pattern_matching::parser::parse;
+    use crate::pattern_matching::{Type, try_eval_in};
+    use std::cell::RefCell;
+
+    /// Keeps every message it is given.
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<String>>);
+
+    impl Logger for Recorder {
+        fn log(&self, verbosity: u8, message: &str) {
+            self.0.borrow_mut().push(format!("{verbosity}: {message}"));
+        }
+    }
+
+    fn env() -> Environment {
+        let mut env = Environment::new();
+        env.set("x", 7);
+        env
+    }
+
+    #[test]
+    fn test_steps() {
+        let recorder = Recorder::default();
+        let e = parse("(3 - 4) * 5").unwrap();
+        assert_eq!(trace(&e, &env(), &recorder), Ok(Value::Number(-5)));
+        assert_eq!(
+            recorder.0.into_inner(),
+            [
+                "1: (3 - 4) * 5",
+                "2: 3 - 4 => -1",
+                "1: -1 * 5",
+                "2: -1 * 5 => -5",
+                "1: -5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verbosity_filter() {
+        let logger = VerbosityFilter {
+            max_verbosity: STEP,
+            inner: Recorder::default(),
+        };
+        let e = parse("-(x - 2) + 2 ^ 3").unwrap();
+        assert_eq!(trace(&e, &env(), &logger), Ok(Value::Number(3)));
+        assert_eq!(
+            logger.inner.0.into_inner(),
+            [
+                "1: -(x - 2) + 2 ^ 3",
+                "1: -(7 - 2) + 2 ^ 3",
+                "1: -(5) + 2 ^ 3",
+                "1: -5 + 2 ^ 3",
+                "1: -5 + 8",
+                "1: 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter() {
+        let recorder = Recorder::default();
+        let logger = Filter::new(&recorder, |verbosity, message: &str| {
+            verbosity == REDUCTION && message.starts_with('x')
+        });
+        let e = parse("x * x - 1").unwrap();
+        assert_eq!(trace(&e, &env(), &logger), Ok(Value::Number(48)));
+        assert_eq!(recorder.0.into_inner(), ["2: x => 7", "2: x => 7"]);
+    }
+
+    #[test]
+    fn test_let_if_fn() {
+        let logger = VerbosityFilter {
+            max_verbosity: STEP,
+            inner: Recorder::default(),
+        };
+        let e = parse("let k = 10 in fn f(a) = a + k in if k > 5 then f(x) else 0").unwrap();
+        assert_eq!(trace(&e, &env(), &logger), Ok(Value::Number(17)));
+        assert_eq!(
+            logger.inner.0.into_inner(),
+            [
+                "1: let k = 10 in fn f(a) = a + k in if k > 5 then f(x) else 0",
+                "1: fn f(a) = a + 10 in if 10 > 5 then f(x) else 0",
+                "1: fn f(a) = a + 10 in if true then f(x) else 0",
+                "1: fn f(a) = a + 10 in f(x)",
+                "1: fn f(a) = a + 10 in f(7)",
+                "1: fn f(a) = a + 10 in 17",
+                "1: 17",
+            ]
+        );
+
+        let recorder = Recorder::default();
+        let e = parse("fn f(n) = f(n) in 1 + f(1 + 1)").unwrap();
+        assert!(matches!(
+            trace(&e, &env(), &recorder),
+            Err(EvalError::RecursionLimit(_))
+        ));
+        assert_eq!(
+            recorder.0.into_inner()[1..],
+            ["2: 1 + 1 => 2", "1: fn f(n) = f(n) in 1 + f(2)"]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let recorder = Recorder::default();
+        let e = parse("1 + 10 / (x - x)").unwrap();
+        assert_eq!(
+            trace(&e, &env(), &recorder),
+            Err(EvalError::DivisionByZero(parse("10 / 0").unwrap()))
+        );
+        assert_eq!(recorder.0.borrow().last().unwrap(), "1: 1 + 10 / 0");
+        assert_eq!(
+            trace(&parse("y + 1").unwrap(), &env(), &recorder),
+            Err(EvalError::UnboundVariable(String::from("y")))
+        );
+        assert_eq!(
+            trace(&parse("if x then 1 else 2").unwrap(), &env(), &recorder),
+            Err(EvalError::TypeMismatch {
+                expression: Expression::Value(7),
+                expected: Type::Bool,
+                found: Type::Number,
+            })
+        );
+    }
+
+    #[test]
+    fn test_matches_try_eval_in() {
+        for input in [
+            "(3 - 4) * 5 + 10 * 9",
+            "x ^ 2 % 5 - -x",
+            "-(-x / 2)",
+            "x",
+            "let x = x + 1 in let y = x * 2 in x + y",
+            "fn f() = 1 in fn g() = f() in fn f() = 2 in g() * 10 + f()",
+            "fn fact(n) = if n <= 1 then 1 else n * fact(n - 1) in fact(x) - fact(x - 1)",
+            "fn f(x) = x in f(1, 2)",
+            "g(1 / 0)",
+            "let a = 1 in fn f(x) = x + a in let a = 100 in f(a)",
+            "if x > 1 == true then x < 10 else false",
+            "let b = x == 7 in if b then 1 - x else 0",
+            "fn f(b) = if b then 1 else 0 in f(true) + f(false)",
+            "if 7 then 1 else 2",
+            "1 + true",
+        ] {
+            let e = parse(input).unwrap();
+            assert_eq!(
+                trace(&e, &env(), &Recorder::default()),
+                try_eval_in(&e, &env())
+            );
+        }
+    }
+}
@@ -0,0 +1,439 @@
+//! Static type checking of an [`Expression`], before it is evaluated.
+//!
+//! Numbers and booleans do not mix: arithmetic and `<`, `<=`, `>`, `>=` take
+//! numbers, `==` and `!=` take two values of the same type, conditions must
+//! be booleans and both branches of an `if` must agree. Parameters and
+//! function results carry no annotations; their types are inferred from how
+//! they are used. A function has a single type, so
+//! `fn id(x) = x in if id(true) then id(1) else 0` is rejected.
+//!
+//! A tree that passes [`check`] evaluates without
+//! [`EvalError::TypeMismatch`](super::EvalError::TypeMismatch), and without
+//! unbound variables, unknown functions or wrong argument counts, as long as
+//! the environment is the one it was checked against.
+
+use std::fmt;
+
+use super::{Comparison, Environment, Expression, Operation, Type};
+
+/// Why an [`Expression`] does not type-check.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeError {
+    /// `expression` has type `found` where `expected` is needed, like the
+    /// `true` in `1 + true`.
+    Mismatch {
+        expression: Expression,
+        expected: Type,
+        found: Type,
+    },
+
+    /// A variable is neither bound around it nor in the environment.
+    UnboundVariable(String),
+
+    /// No function of this name is in scope.
+    UnknownFunction(String),
+
+    /// A call passes a different number of arguments than the function has
+    /// parameters.
+    ArgumentCount { call: Expression, expected: usize },
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeError::Mismatch {
+                expression,
+                expected,
+                found,
+            } => write!(f, "expected {expected}, but `{expression}` is {found}"),
+            TypeError::UnboundVariable(name) => write!(f, "unbound variable `{name}`"),
+            TypeError::UnknownFunction(name) => write!(f, "unknown function `{name}`"),
+            TypeError::ArgumentCount { call, expected } => {
+                write!(f, "`{call}` passes the wrong number of arguments, ")?;
+                write!(f, "expected {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+/// The type of `e`, with its free variables typed by their values in `env`.
+///
+/// An expression whose type is never pinned down, such as a call of a
+/// function that does nothing but recurse, never produces a value; it is
+/// reported as a number.
+pub fn check(e: &Expression, env: &Environment) -> Result<Type, TypeError> {
+    let mut checker = Checker {
+        env,
+        vars: Vec::new(),
+        scope: Vec::new(),
+    };
+    let ty = checker.infer(e)?;
+    match checker.resolve(ty) {
+        Ty::Known(ty) => Ok(ty),
+        Ty::Var(_) => Ok(Type::Number),
+    }
+}
+
+/// A type as far as it is known: either settled, or a variable standing for a
+/// type that later uses may settle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Ty {
+    Known(Type),
+    Var(usize),
+}
+
+const NUMBER: Ty = Ty::Known(Type::Number);
+const BOOL: Ty = Ty::Known(Type::Bool);
+
+/// What a name in scope stands for.
+enum Binding {
+    Var(Ty),
+    Function { params: Vec<Ty>, result: Ty },
+}
+
+struct Checker<'a> {
+    env: &'a Environment,
+    /// What each type variable has been unified with, if anything yet.
+    vars: Vec<Option<Ty>>,
+    /// The `let`s, parameters and functions around the current expression,
+    /// innermost last.
+    scope: Vec<(&'a str, Binding)>,
+}
+
+impl<'a> Checker<'a> {
+    fn fresh(&mut self) -> Ty {
+        self.vars.push(None);
+        Ty::Var(self.vars.len() - 1)
+    }
+
+    /// Follow the variables `ty` has been unified with.
+    fn resolve(&self, mut ty: Ty) -> Ty {
+        while let Ty::Var(var) = ty
+            && let Some(bound) = self.vars[var]
+        {
+            ty = bound;
+        }
+        ty
+    }
+
+    /// Make `found`, the type of `e`, agree with `expected`.
+    fn unify(&mut self, found: Ty, expected: Ty, e: &Expression) -> Result<(), TypeError> {
+        match (self.resolve(found), self.resolve(expected)) {
+            (Ty::Known(found), Ty::Known(expected)) if found != expected => {
+                Err(TypeError::Mismatch {
+                    expression: e.clone(),
+                    expected,
+                    found,
+                })
+            }
+            (Ty::Var(var), other) | (other, Ty::Var(var)) if other != Ty::Var(var) => {
+                self.vars[var] = Some(other);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn expect(&mut self, e: &'a Expression, expected: Ty) -> Result<(), TypeError> {
+        let found = self.infer(e)?;
+        self.unify(found, expected, e)
+    }
+
+    /// The type of `op` applied to `left`, already inferred to have type
+    /// `left_ty`, and `right`.
+    fn operation(
+        &mut self,
+        op: Operation,
+        left: &Expression,
+        left_ty: Ty,
+        right: &'a Expression,
+    ) -> Result<Ty, TypeError> {
+        match op {
+            Operation::Compare(Comparison::Eq | Comparison::Ne) => {
+                self.expect(right, left_ty)?;
+                Ok(BOOL)
+            }
+            _ => {
+                self.unify(left_ty, NUMBER, left)?;
+                self.expect(right, NUMBER)?;
+                match op {
+                    Operation::Compare(_) => Ok(BOOL),
+                    _ => Ok(NUMBER),
+                }
+            }
+        }
+    }
+
+    fn infer(&mut self, e: &'a Expression) -> Result<Ty, TypeError> {
+        match e {
+            Expression::Op { .. } => {
+                // Left operands first, innermost up, so that a long chain is
+                // checked in a loop.
+                let (spine, bottom) = e.left_spine();
+                let mut ty = self.infer(bottom)?;
+                for (op, left, right) in spine.into_iter().rev() {
+                    ty = self.operation(op, left, ty, right)?;
+                }
+                Ok(ty)
+            }
+            Expression::Neg(operand) => {
+                self.expect(operand, NUMBER)?;
+                Ok(NUMBER)
+            }
+            Expression::Value(_) => Ok(NUMBER),
+            Expression::Bool(_) => Ok(BOOL),
+            Expression::Var(name) => {
+                let bound = self
+                    .scope
+                    .iter()
+                    .rev()
+                    .find_map(|(n, binding)| match binding {
+                        Binding::Var(ty) if n == name => Some(*ty),
+                        _ => None,
+                    });
+                match bound {
+                    Some(ty) => Ok(ty),
+                    None => match self.env.get(name) {
+                        Some(value) => Ok(Ty::Known(value.type_of())),
+                        None => Err(TypeError::UnboundVariable(name.clone())),
+                    },
+                }
+            }
+            Expression::Let { name, value, body } => {
+                let ty = self.infer(value)?;
+                self.scope.push((name, Binding::Var(ty)));
+                let ty = self.infer(body)?;
+                self.scope.pop();
+                Ok(ty)
+            }
+            Expression::If {
+                condition,
+                consequent,
+                alternative,
+            } => {
+                self.expect(condition, BOOL)?;
+                let ty = self.infer(consequent)?;
+                self.expect(alternative, ty)?;
+                Ok(ty)
+            }
+            Expression::Function {
+                name,
+                params,
+                body,
+                rest,
+            } => {
+                let param_types: Vec<_> = params.iter().map(|_| self.fresh()).collect();
+                let result = self.fresh();
+                let function = Binding::Function {
+                    params: param_types.clone(),
+                    result,
+                };
+                self.scope.push((name, function));
+                let depth = self.scope.len();
+                // The body sees the function itself, so it can recurse.
+                for (param, ty) in params.iter().zip(param_types) {
+                    self.scope.push((param, Binding::Var(ty)));
+                }
+                self.expect(body, result)?;
+                self.scope.truncate(depth);
+                let ty = self.infer(rest)?;
+                self.scope.pop();
+                Ok(ty)
+            }
+            Expression::Call { name, args } => {
+                let function = self
+                    .scope
+                    .iter()
+                    .rev()
+                    .find_map(|(n, binding)| match binding {
+                        Binding::Function { params, result } if n == name => {
+                            Some((params.clone(), *result))
+                        }
+                        _ => None,
+                    });
+                let Some((params, result)) = function else {
+                    return Err(TypeError::UnknownFunction(name.clone()));
+                };
+                if params.len() != args.len() {
+                    return Err(TypeError::ArgumentCount {
+                        call: e.clone(),
+                        expected: params.len(),
+                    });
+                }
+                for (arg, ty) in args.iter().zip(params) {
+                    self.expect(arg, ty)?;
+                }
+                Ok(result)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern_matching::parser::parse;
+    use crate::pattern_matching::{EvalError, try_eval_in};
+
+    fn env() -> Environment {
+        let mut env = Environment::new();
+        env.set("x", 7);
+        env.set("flag", true);
+        env
+    }
+
+    fn type_of(input: &str) -> Result<Type, TypeError> {
+        check(&parse(input).unwrap(), &env())
+    }
+
+    fn mismatch(input: &str, expected: Type, found: Type) -> Result<Type, TypeError> {
+        Err(TypeError::Mismatch {
+            expression: parse(input).unwrap(),
+            expected,
+            found,
+        })
+    }
+
+    #[test]
+    fn test_well_typed() {
+        assert_eq!(type_of("(3 - x) * 5"), Ok(Type::Number));
+        assert_eq!(type_of("x < 3 == flag"), Ok(Type::Bool));
+        assert_eq!(type_of("if flag then x else -x"), Ok(Type::Number));
+        assert_eq!(type_of("let b = x > 0 in b != flag"), Ok(Type::Bool));
+        assert_eq!(
+            type_of("fn fact(n) = if n <= 1 then 1 else n * fact(n - 1) in fact(x)"),
+            Ok(Type::Number)
+        );
+        // Parameter types come from the body or, failing that, the calls.
+        assert_eq!(
+            type_of("fn not(b) = b == false in not(flag)"),
+            Ok(Type::Bool)
+        );
+        assert_eq!(type_of("fn id(v) = v in id(true)"), Ok(Type::Bool));
+        assert_eq!(
+            type_of("fn pick(c, a, b) = if c then a else b in pick(flag, 1, 2)"),
+            Ok(Type::Number)
+        );
+        assert_eq!(type_of("fn loop(n) = loop(n) in loop(1)"), Ok(Type::Number));
+        assert_eq!(type_of("fn unused(v) = v in 1"), Ok(Type::Number));
+    }
+
+    #[test]
+    fn test_mismatches() {
+        use Type::{Bool, Number};
+        assert_eq!(type_of("1 + true"), mismatch("true", Number, Bool));
+        assert_eq!(type_of("flag * 2"), mismatch("flag", Number, Bool));
+        assert_eq!(type_of("-(x < 1)"), mismatch("x < 1", Number, Bool));
+        assert_eq!(type_of("true < false"), mismatch("true", Number, Bool));
+        assert_eq!(type_of("x == flag"), mismatch("flag", Number, Bool));
+        assert_eq!(type_of("if x then 1 else 2"), mismatch("x", Bool, Number));
+        assert_eq!(
+            type_of("if flag then 1 else flag"),
+            mismatch("flag", Number, Bool)
+        );
+        // The untaken branch is checked too.
+        assert_eq!(
+            type_of("if true then 1 else 2 + true"),
+            mismatch("true", Number, Bool)
+        );
+        assert_eq!(
+            type_of("fn f(n) = if n then 1 else 0 in f(3)"),
+            mismatch("3", Bool, Number)
+        );
+        assert_eq!(
+            type_of("fn id(v) = v in if id(true) then id(1) else 0"),
+            mismatch("1", Bool, Number)
+        );
+        assert_eq!(
+            type_of("fn f(a, b) = a == b in f(1, flag)"),
+            mismatch("flag", Number, Bool)
+        );
+        assert_eq!(
+            type_of("fn f(n) = if n > 0 then f(n - 1) else false in f(3) + 1"),
+            mismatch("f(3)", Number, Bool)
+        );
+
+        let err = type_of("let y = 1 < 2 in x + y * 3").unwrap_err();
+        assert_eq!(err.to_string(), "expected a number, but `y` is a boolean");
+    }
+
+    #[test]
+    fn test_scope_errors() {
+        assert_eq!(
+            type_of("let y = 1 in y + z"),
+            Err(TypeError::UnboundVariable(String::from("z")))
+        );
+        assert_eq!(
+            type_of("(fn f() = 1 in f()) + f()"),
+            Err(TypeError::UnknownFunction(String::from("f")))
+        );
+        assert_eq!(
+            type_of("fn f(a) = a in f(1, 2)"),
+            Err(TypeError::ArgumentCount {
+                call: parse("f(1, 2)").unwrap(),
+                expected: 1
+            })
+        );
+    }
+
+    /// Every expression of at most `depth` levels over a few leaves, built
+    /// from `+`, `<`, `==`, negation and, at the top, `if`.
+    fn expressions(depth: u32) -> Vec<Expression> {
+        let mut all: Vec<Expression> = ["1", "true", "x", "flag"]
+            .iter()
+            .map(|leaf| parse(leaf).unwrap())
+            .collect();
+        for level in 0..depth {
+            let smaller = all.clone();
+            for left in &smaller {
+                all.push(Expression::Neg(Box::new(left.clone())));
+                for right in &smaller {
+                    for op in [
+                        Operation::Add,
+                        Operation::Compare(Comparison::Lt),
+                        Operation::Compare(Comparison::Eq),
+                    ] {
+                        all.push(Expression::Op {
+                            op,
+                            left: Box::new(left.clone()),
+                            right: Box::new(right.clone()),
+                        });
+                    }
+                    if level + 1 == depth {
+                        all.push(Expression::If {
+                            condition: Box::new(left.clone()),
+                            consequent: Box::new(right.clone()),
+                            alternative: Box::new(smaller[smaller.len() / 2].clone()),
+                        });
+                    }
+                }
+            }
+        }
+        all
+    }
+
+    #[test]
+    fn test_checked_trees_have_no_runtime_type_errors() {
+        let env = env();
+        let (mut accepted, mut rejected) = (0, 0);
+        for e in expressions(2) {
+            let checked = check(&e, &env);
+            let evaluated = try_eval_in(&e, &env);
+            if checked.is_ok() {
+                accepted += 1;
+                assert!(
+                    !matches!(evaluated, Err(EvalError::TypeMismatch { .. })),
+                    "{e} type-checked but evaluated to {evaluated:?}"
+                );
+                if let (Ok(ty), Ok(value)) = (checked, evaluated) {
+                    assert_eq!(ty, value.type_of(), "{e}");
+                }
+            } else {
+                rejected += 1;
+            }
+        }
+        assert!(accepted > 100 && rejected > 1000, "{accepted} {rejected}");
+    }
+}
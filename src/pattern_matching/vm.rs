@@ -0,0 +1,558 @@
+//! Compiles an [`Expression`] into a flat instruction sequence and runs it on
+//! a small stack machine.
+//!
+//! Neither compiling nor running recurses on the native stack, so this copes
+//! with trees far deeper than [`try_eval_in`](super::try_eval_in) does.
+//!
+//! `let`s and parameters live in numbered slots of a call frame. Every frame
+//! links to the frame of the function its function was defined in, so a body
+//! reaches the variables around its definition by following those links,
+//! however deep the calls that led to it are.
+
+use super::number::Checked;
+use super::{
+    Comparison, Environment, EvalError, EvalResult, Expression, Operation, Value, negate, operate,
+};
+
+/// A single stack machine instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    /// Push a literal value.
+    Push(Value),
+    /// Push the value bound to a variable in the environment.
+    Load(String),
+    /// Push the value in `slot` of the frame `hops` definitions out from the
+    /// current one.
+    LoadLocal { hops: usize, slot: usize },
+    /// Pop a value into a slot of the current frame.
+    Store(usize),
+    /// Pop two values and push their sum.
+    Add,
+    /// Pop two values and push their difference.
+    Sub,
+    /// Pop two values and push their product.
+    Mul,
+    /// Pop two values and push their quotient.
+    Div,
+    /// Pop two values and push the remainder of their division.
+    Mod,
+    /// Pop two values and push the first raised to the power of the second.
+    Pow,
+    /// Pop two values and push whether the comparison holds.
+    Compare(Comparison),
+    /// Pop a value and push its negation.
+    Neg,
+    /// Continue at the given instruction.
+    Jump(usize),
+    /// Pop a boolean and continue at the given instruction if it is false.
+    JumpIfFalse(usize),
+    /// Pop a function's arguments and run its body in a new frame, whose
+    /// definition is `hops` definitions out from the current frame.
+    Call { function: usize, hops: usize },
+    /// Leave the current frame, keeping the value on top of the stack.
+    Return,
+    /// Fail with an error found while compiling, such as a call of an unknown
+    /// function, once evaluation gets this far.
+    Fail(EvalError),
+}
+
+/// A compiled expression, ready to be run against any number of environments.
+#[derive(Debug)]
+pub struct Program<'a> {
+    code: Vec<Instruction>,
+    /// The sub-expression each instruction was compiled from, parallel to
+    /// `code`. Errors report these so they match the tree-walking evaluator.
+    origins: Vec<&'a Expression>,
+    functions: Vec<Function>,
+    /// The number of slots the outermost frame needs.
+    slots: usize,
+}
+
+/// Where a compiled function starts and the frame it needs.
+#[derive(Debug)]
+struct Function {
+    entry: usize,
+    params: usize,
+    slots: usize,
+}
+
+/// Work left to do while compiling, in the order it has to happen.
+enum Task<'a> {
+    Compile(&'a Expression),
+    Emit(Instruction, &'a Expression),
+    /// Bind a `let` to a new slot, storing the value on top of the stack.
+    Bind(&'a str, &'a Expression),
+    /// Forget the latest binding of the innermost function.
+    Unbind,
+    /// Mark the next instruction as the target of a label.
+    Label(usize),
+    /// Start the body of a function, with its parameters in the first slots.
+    Enter(&'a [String]),
+    /// Finish the body of the function with the given index.
+    Leave(usize),
+}
+
+/// What a name is bound to while compiling.
+#[derive(Clone, Copy)]
+enum Binding {
+    Var(usize),
+    Function(usize),
+}
+
+/// The bindings of one function body, or of the whole expression.
+#[derive(Default)]
+struct Level<'a> {
+    bindings: Vec<(&'a str, Binding)>,
+    slots: usize,
+}
+
+/// Find the innermost binding of `name` that `select` accepts, and how many
+/// levels out it is.
+fn resolve<T>(
+    levels: &[Level],
+    name: &str,
+    select: impl Fn(Binding) -> Option<T>,
+) -> Option<(usize, T)> {
+    levels.iter().rev().enumerate().find_map(|(hops, level)| {
+        let mut bindings = level.bindings.iter().rev();
+        bindings.find_map(|&(n, binding)| select(binding).filter(|_| n == name).map(|t| (hops, t)))
+    })
+}
+
+/// Compile an expression into a [`Program`].
+pub fn compile(e: &Expression) -> Program<'_> {
+    let mut program = Program {
+        code: Vec::new(),
+        origins: Vec::new(),
+        functions: Vec::new(),
+        slots: 0,
+    };
+    // Jump targets are label numbers until every label has an address.
+    let mut labels = Vec::new();
+    let mut new_label = || {
+        labels.push(0);
+        labels.len() - 1
+    };
+    let mut label_addresses = Vec::new();
+    let mut levels = vec![Level::default()];
+
+    // An explicit walk: tasks are pushed in reverse so they pop in order.
+    let mut pending = vec![Task::Compile(e)];
+    while let Some(task) = pending.pop() {
+        let (instruction, e) = match task {
+            Task::Compile(e) => {
+                let instruction = match e {
+                    Expression::Op { op, left, right } => {
+                        let instruction = match op {
+                            Operation::Add => Instruction::Add,
+                            Operation::Sub => Instruction::Sub,
+                            Operation::Mul => Instruction::Mul,
+                            Operation::Div => Instruction::Div,
+                            Operation::Mod => Instruction::Mod,
+                            Operation::Pow => Instruction::Pow,
+                            Operation::Compare(c) => Instruction::Compare(*c),
+                        };
+                        pending.push(Task::Emit(instruction, e));
+                        pending.push(Task::Compile(right));
+                        pending.push(Task::Compile(left));
+                        continue;
+                    }
+                    Expression::Neg(operand) => {
+                        pending.push(Task::Emit(Instruction::Neg, e));
+                        pending.push(Task::Compile(operand));
+                        continue;
+                    }
+                    Expression::Value(v) => Instruction::Push(Value::Number(*v)),
+                    Expression::Bool(b) => Instruction::Push(Value::Bool(*b)),
+                    Expression::Var(name) => {
+                        match resolve(&levels, name, |b| match b {
+                            Binding::Var(slot) => Some(slot),
+                            Binding::Function(_) => None,
+                        }) {
+                            Some((hops, slot)) => Instruction::LoadLocal { hops, slot },
+                            None => Instruction::Load(name.clone()),
+                        }
+                    }
+                    Expression::Let { name, value, body } => {
+                        pending.push(Task::Unbind);
+                        pending.push(Task::Compile(body));
+                        pending.push(Task::Bind(name, e));
+                        pending.push(Task::Compile(value));
+                        continue;
+                    }
+                    Expression::If {
+                        condition,
+                        consequent,
+                        alternative,
+                    } => {
+                        let (otherwise, end) = (new_label(), new_label());
+                        pending.push(Task::Label(end));
+                        pending.push(Task::Compile(alternative));
+                        pending.push(Task::Label(otherwise));
+                        pending.push(Task::Emit(Instruction::Jump(end), e));
+                        pending.push(Task::Compile(consequent));
+                        // A condition that is not a boolean is reported as such.
+                        let jump = Instruction::JumpIfFalse(otherwise);
+                        pending.push(Task::Emit(jump, condition));
+                        pending.push(Task::Compile(condition));
+                        continue;
+                    }
+                    Expression::Function {
+                        name,
+                        params,
+                        body,
+                        rest,
+                    } => {
+                        let (entry, after) = (new_label(), new_label());
+                        let function = program.functions.len();
+                        program.functions.push(Function {
+                            entry,
+                            params: params.len(),
+                            slots: 0,
+                        });
+                        let level = levels.last_mut().expect("the outermost level stays");
+                        level.bindings.push((name, Binding::Function(function)));
+                        pending.push(Task::Unbind);
+                        pending.push(Task::Compile(rest));
+                        pending.push(Task::Label(after));
+                        pending.push(Task::Leave(function));
+                        pending.push(Task::Emit(Instruction::Return, e));
+                        pending.push(Task::Compile(body));
+                        pending.push(Task::Enter(params));
+                        pending.push(Task::Label(entry));
+                        pending.push(Task::Emit(Instruction::Jump(after), e));
+                        continue;
+                    }
+                    Expression::Call { name, args } => {
+                        let found = resolve(&levels, name, |b| match b {
+                            Binding::Function(function) => Some(function),
+                            Binding::Var(_) => None,
+                        });
+                        match found {
+                            None => Instruction::Fail(EvalError::UnknownFunction(name.clone())),
+                            Some((_, function))
+                                if program.functions[function].params != args.len() =>
+                            {
+                                Instruction::Fail(EvalError::ArgumentCount {
+                                    call: e.clone(),
+                                    expected: program.functions[function].params,
+                                })
+                            }
+                            Some((hops, function)) => {
+                                pending.push(Task::Emit(Instruction::Call { function, hops }, e));
+                                pending.extend(args.iter().rev().map(Task::Compile));
+                                continue;
+                            }
+                        }
+                    }
+                };
+                (instruction, e)
+            }
+            Task::Emit(instruction, e) => (instruction, e),
+            Task::Bind(name, e) => {
+                let level = levels.last_mut().expect("the outermost level stays");
+                let slot = level.slots;
+                level.slots += 1;
+                level.bindings.push((name, Binding::Var(slot)));
+                (Instruction::Store(slot), e)
+            }
+            Task::Unbind => {
+                let level = levels.last_mut().expect("the outermost level stays");
+                level.bindings.pop();
+                continue;
+            }
+            Task::Label(label) => {
+                label_addresses.push((label, program.code.len()));
+                continue;
+            }
+            Task::Enter(params) => {
+                levels.push(Level {
+                    bindings: (params.iter().enumerate())
+                        .map(|(slot, name)| (name.as_str(), Binding::Var(slot)))
+                        .collect(),
+                    slots: params.len(),
+                });
+                continue;
+            }
+            Task::Leave(function) => {
+                let level = levels.pop().expect("entered before");
+                program.functions[function].slots = level.slots;
+                continue;
+            }
+        };
+        program.code.push(instruction);
+        program.origins.push(e);
+    }
+
+    for (label, address) in label_addresses {
+        labels[label] = address;
+    }
+    for instruction in &mut program.code {
+        if let Instruction::Jump(target) | Instruction::JumpIfFalse(target) = instruction {
+            *target = labels[*target];
+        }
+    }
+    for function in &mut program.functions {
+        function.entry = labels[function.entry];
+    }
+    program.slots = levels[0].slots;
+    program
+}
+
+/// The locals of a function call, or of the whole expression.
+struct Frame {
+    slots: Vec<Value>,
+    /// The index of the frame the called function was defined in.
+    definition: usize,
+    /// Where to continue once the call returns.
+    return_to: usize,
+}
+
+impl Program<'_> {
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.code
+    }
+
+    /// Run the program, looking free variables up in `env`.
+    pub fn run(&self, env: &Environment) -> EvalResult<Value> {
+        let pop = |stack: &mut Vec<Value>| {
+            stack
+                .pop()
+                .expect("operands are pushed before their operation")
+        };
+        // The index of the frame `hops` definitions out from the current one.
+        let frame_out = |frames: &[Frame], hops: usize| {
+            (0..hops).fold(frames.len() - 1, |frame, _| frames[frame].definition)
+        };
+
+        let mut stack = Vec::new();
+        let mut frames = vec![Frame {
+            slots: vec![Value::Number(0); self.slots],
+            definition: 0,
+            return_to: self.code.len(),
+        }];
+        let mut pc = 0;
+        while let Some(instruction) = self.code.get(pc) {
+            let origin = self.origins[pc];
+            pc += 1;
+            let op = match instruction {
+                Instruction::Push(v) => {
+                    stack.push(*v);
+                    continue;
+                }
+                Instruction::Load(name) => {
+                    let value = env
+                        .get(name)
+                        .ok_or_else(|| EvalError::UnboundVariable(name.clone()))?;
+                    stack.push(value);
+                    continue;
+                }
+                Instruction::LoadLocal { hops, slot } => {
+                    stack.push(frames[frame_out(&frames, *hops)].slots[*slot]);
+                    continue;
+                }
+                Instruction::Store(slot) => {
+                    let value = pop(&mut stack);
+                    frames.last_mut().expect("a frame is running").slots[*slot] = value;
+                    continue;
+                }
+                Instruction::Add => Operation::Add,
+                Instruction::Sub => Operation::Sub,
+                Instruction::Mul => Operation::Mul,
+                Instruction::Div => Operation::Div,
+                Instruction::Mod => Operation::Mod,
+                Instruction::Pow => Operation::Pow,
+                Instruction::Compare(c) => Operation::Compare(*c),
+                Instruction::Neg => {
+                    let operand = pop(&mut stack);
+                    stack.push(negate(&Checked, operand).map_err(|err| err.at(origin))?);
+                    continue;
+                }
+                Instruction::Jump(target) => {
+                    pc = *target;
+                    continue;
+                }
+                Instruction::JumpIfFalse(target) => {
+                    let taken = pop(&mut stack).boolean().map_err(|err| err.at(origin))?;
+                    if !taken {
+                        pc = *target;
+                    }
+                    continue;
+                }
+                Instruction::Call { function, hops } => {
+                    // Every frame but the outermost is a call in progress.
+                    if frames.len() > env.max_depth() {
+                        return Err(EvalError::RecursionLimit(origin.clone()));
+                    }
+                    let function = &self.functions[*function];
+                    let mut slots = stack.split_off(stack.len() - function.params);
+                    slots.resize(function.slots, Value::Number(0));
+                    frames.push(Frame {
+                        slots,
+                        definition: frame_out(&frames, *hops),
+                        return_to: pc,
+                    });
+                    pc = function.entry;
+                    continue;
+                }
+                Instruction::Return => {
+                    pc = frames.pop().expect("a call is running").return_to;
+                    continue;
+                }
+                Instruction::Fail(err) => return Err(err.clone()),
+            };
+            let right = pop(&mut stack);
+            let left = pop(&mut stack);
+            stack.push(operate(&Checked, op, left, right).map_err(|err| err.at(origin))?);
+        }
+        Ok(stack.pop().expect("a program leaves exactly one value"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern_matching::parser::parse;
+    use crate::pattern_matching::try_eval_in;
+    use crate::random::Rng;
+
+    /// A random tree of arithmetic, mixing in extreme literals and an unbound
+    /// variable so that errors come up too.
+    fn generate(rng: &mut Rng, depth: u32) -> Expression {
+        if depth > 0 && rng.below(8) == 0 {
+            return Expression::Neg(Box::new(generate(rng, depth - 1)));
+        }
+        if depth == 0 || rng.below(4) == 0 {
+            return match rng.below(5) {
+                0 => Expression::Var(["x", "y", "unbound"][rng.below(3) as usize].to_string()),
+                1 => Expression::Value([i64::MIN, i64::MAX, 0][rng.below(3) as usize]),
+                _ => Expression::Value(rng.below(21) as i64 - 10),
+            };
+        }
+        let op = [
+            Operation::Add,
+            Operation::Sub,
+            Operation::Mul,
+            Operation::Div,
+            Operation::Mod,
+            Operation::Pow,
+        ][rng.below(6) as usize];
+        Expression::Op {
+            op,
+            left: Box::new(generate(rng, depth - 1)),
+            right: Box::new(generate(rng, depth - 1)),
+        }
+    }
+
+    #[test]
+    fn test_compile() {
+        let e = parse("(3 - x) * 5").unwrap();
+        assert_eq!(
+            compile(&e).instructions(),
+            [
+                Instruction::Push(Value::Number(3)),
+                Instruction::Load(String::from("x")),
+                Instruction::Sub,
+                Instruction::Push(Value::Number(5)),
+                Instruction::Mul,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let e = parse("(3 - 4) * 5 + 10 * 9").unwrap();
+        assert_eq!(compile(&e).run(&Environment::new()), Ok(Value::Number(85)));
+    }
+
+    #[test]
+    fn test_errors_name_the_failing_operation() {
+        let e = parse("1 + 10 / (x - x)").unwrap();
+        let mut env = Environment::new();
+        env.set("x", 4);
+        assert_eq!(
+            compile(&e).run(&env),
+            Err(EvalError::DivisionByZero(parse("10 / (x - x)").unwrap()))
+        );
+    }
+
+    #[test]
+    fn test_matches_tree_walker() {
+        let mut rng = Rng::new(42);
+        let mut env = Environment::new();
+        env.set("x", 7);
+        env.set("y", -3);
+        for _ in 0..2000 {
+            let e = generate(&mut rng, 6);
+            assert_eq!(compile(&e).run(&env), try_eval_in(&e, &env), "{e:?}");
+        }
+    }
+
+    #[test]
+    fn test_let_if_fn() {
+        let e = parse("let x = 2 in if x < 3 then x else 0").unwrap();
+        assert_eq!(
+            compile(&e).instructions(),
+            [
+                Instruction::Push(Value::Number(2)),
+                Instruction::Store(0),
+                Instruction::LoadLocal { hops: 0, slot: 0 },
+                Instruction::Push(Value::Number(3)),
+                Instruction::Compare(Comparison::Lt),
+                Instruction::JumpIfFalse(8),
+                Instruction::LoadLocal { hops: 0, slot: 0 },
+                Instruction::Jump(9),
+                Instruction::Push(Value::Number(0)),
+            ]
+        );
+
+        let mut env = Environment::new();
+        env.set("x", 5);
+        for input in [
+            "let x = 3 in let y = x * x in y + x",
+            "let x = 1 in (let x = 2 in x) + x",
+            "if x > 4 then 1 / 0 else 7",
+            "if x < 4 then 1 / 0 else 7",
+            "fn fact(n) = if n <= 1 then 1 else n * fact(n - 1) in fact(x)",
+            "let k = 10 in fn add_k(x) = x + k in let k = 100 in add_k(1)",
+            "fn f() = 1 in fn g() = f() in fn f() = 2 in g() * 10 + f()",
+            "fn outer(a) = fn inner(b) = if b > 0 then a + inner(b - 1) else 0 in inner(3) in outer(x)",
+            "fn fib(n) = if n < 2 then n else fib(n - 1) + fib(n - 2) in fib(x + 10)",
+            "fn f(x) = x in f(1, 2)",
+            "g(1 / 0)",
+            "fn f(n) = f(n + 1) in f(0)",
+            "fn f(n) = if n != 0 then f(n - 1) else unbound in 1 + f(3)",
+            "if x then 1 else 2",
+            "let b = x > 2 in if b == true then b else -b",
+            "fn f(a, b) = a == b in if f(true, x < 3) then 1 else f(1, 2) + 1",
+            "fn f(a, b) = a - b in f(x, 2) * f(2, x)",
+        ] {
+            let e = parse(input).unwrap();
+            assert_eq!(compile(&e).run(&env), try_eval_in(&e, &env), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_deep_recursion() {
+        // Frames live on the heap, so only the limit bounds the depth.
+        let e = parse("fn down(n) = if n > 0 then 1 + down(n - 1) else 0 in down(100000)").unwrap();
+        let mut env = Environment::new();
+        assert!(matches!(
+            compile(&e).run(&env),
+            Err(EvalError::RecursionLimit(_))
+        ));
+        env.set_max_depth(100_001);
+        assert_eq!(compile(&e).run(&env), Ok(Value::Number(100_000)));
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        // Deep enough to overflow a test thread's stack in `try_eval_in`.
+        let depth = 10_000;
+        let e = parse(&format!("{}1", "1 + ".repeat(depth - 1))).unwrap();
+        assert_eq!(
+            compile(&e).run(&Environment::new()),
+            Ok(Value::Number(depth as i64))
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//! A small seeded pseudo-random number generator, so generated test inputs
+//! and simulations can be reproduced from a seed without a dependency.
+
+use std::ops::RangeInclusive;
+
+/// SplitMix64: fast, statistically decent, and not for cryptography.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`. The slight bias of taking a remainder is
+    /// negligible for the small `n` this is meant for.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is zero.
+    pub fn below(&mut self, n: u64) -> u64 {
+        assert!(n > 0, "cannot pick below zero");
+        self.next_u64() % n
+    }
+
+    /// A number in `range`.
+    ///
+    /// # Panics
+    ///
+    /// If `range` is empty.
+    pub fn in_range(&mut self, range: RangeInclusive<i64>) -> i64 {
+        let (start, end) = range.into_inner();
+        assert!(start <= end, "cannot pick from an empty range");
+        let span = end.abs_diff(start);
+        match span.checked_add(1) {
+            Some(n) => start.wrapping_add_unsigned(self.below(n)),
+            None => self.next_u64() as i64,
+        }
+    }
+
+    /// `true` with a probability of `percent` in a hundred.
+    pub fn chance(&mut self, percent: u32) -> bool {
+        self.below(100) < u64::from(percent)
+    }
+
+    /// One of `items`, each picked in proportion to its weight.
+    ///
+    /// # Panics
+    ///
+    /// If the weights add up to zero.
+    pub fn weighted<'a, T>(&mut self, items: &'a [(T, u32)]) -> &'a T {
+        let total: u64 = items.iter().map(|&(_, weight)| u64::from(weight)).sum();
+        let mut pick = self.below(total);
+        for (item, weight) in items {
+            match pick.checked_sub(u64::from(*weight)) {
+                Some(rest) => pick = rest,
+                None => return item,
+            }
+        }
+        unreachable!("the pick is below the total weight")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng() {
+        let draws = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..100).map(|_| rng.below(6)).collect::<Vec<_>>()
+        };
+        assert_eq!(draws(7), draws(7));
+        assert_ne!(draws(7), draws(8));
+        assert!(draws(7).iter().all(|&n| n < 6));
+
+        let mut rng = Rng::new(1);
+        for _ in 0..1000 {
+            assert!((-3..=3).contains(&rng.in_range(-3..=3)));
+            assert_eq!(*rng.weighted(&[('a', 0), ('b', 2), ('c', 0)]), 'b');
+        }
+        rng.in_range(i64::MIN..=i64::MAX);
+        assert!(!rng.chance(0) && rng.chance(100));
+    }
+}
@@ -62,23 +62,76 @@ pub struct BinaryTree<T: Ord> {
 }
 
 impl<T: Ord> BinaryTree<T> {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             root: Subtree::new(),
         }
     }
 
-    fn insert(&mut self, value: T) {
+    pub fn insert(&mut self, value: T) {
         self.root.insert(value);
     }
 
-    fn has(&self, value: &T) -> bool {
+    pub fn has(&self, value: &T) -> bool {
         self.root.has(value)
     }
 
-    fn len(&self) -> usize {
+    pub fn len(&self) -> usize {
         self.root.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.0.is_none()
+    }
+
+    /// Remove `value`, returning whether it was there.
+    pub fn remove(&mut self, value: &T) -> bool {
+        self.root.take(value).is_some()
+    }
+
+    pub fn min(&self) -> Option<&T> {
+        let mut node = self.root.0.as_deref()?;
+        while let Some(left) = node.left.0.as_deref() {
+            node = left;
+        }
+        Some(&node.value)
+    }
+
+    pub fn max(&self) -> Option<&T> {
+        let mut node = self.root.0.as_deref()?;
+        while let Some(right) = node.right.0.as_deref() {
+            node = right;
+        }
+        Some(&node.value)
+    }
+
+    /// The values in ascending order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut iter = Iter { stack: Vec::new() };
+        iter.push_left(&self.root);
+        iter
+    }
+
+    /// The values with each node before its left and then its right subtree.
+    pub fn pre_order(&self) -> PreOrder<'_, T> {
+        PreOrder {
+            stack: self.root.0.as_deref().into_iter().collect(),
+        }
+    }
+
+    /// The values with each node after its left and then its right subtree.
+    pub fn post_order(&self) -> PostOrder<'_, T> {
+        let root = self.root.0.as_deref().map(|node| (node, false));
+        PostOrder {
+            stack: root.into_iter().collect(),
+        }
+    }
+}
+
+impl<T: Ord> Default for BinaryTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: Ord> Subtree<T> {
@@ -114,6 +167,159 @@ impl<T: Ord> Subtree<T> {
             Some(n) => 1 + n.left.len() + n.right.len(),
         }
     }
+
+    /// Take the stored value equal to `value` out of the subtree.
+    fn take(&mut self, value: &T) -> Option<T> {
+        let mut subtree = self;
+        loop {
+            match value.cmp(&subtree.0.as_ref()?.value) {
+                Ordering::Less => subtree = &mut subtree.0.as_mut()?.left,
+                Ordering::Equal => break,
+                Ordering::Greater => subtree = &mut subtree.0.as_mut()?.right,
+            }
+        }
+        let Node { value, left, right } = *subtree.0.take()?;
+        subtree.0 = match (left.0, right.0) {
+            (None, right) => right,
+            (left, None) => left,
+            // The smallest value on the right takes the removed one's place.
+            (left, right) => {
+                let mut right = Subtree(right);
+                right.pop_min().map(|mut successor| {
+                    successor.left = Subtree(left);
+                    successor.right = right;
+                    successor
+                })
+            }
+        };
+        Some(value)
+    }
+
+    /// Take out the node with the smallest value.
+    fn pop_min(&mut self) -> Option<Box<Node<T>>> {
+        let mut subtree = self;
+        while subtree.0.as_ref()?.left.0.is_some() {
+            subtree = &mut subtree.0.as_mut()?.left;
+        }
+        let mut n = subtree.0.take()?;
+        subtree.0 = n.right.0.take();
+        Some(n)
+    }
+}
+
+/// Borrowing in-order iterator over a [`BinaryTree`].
+pub struct Iter<'a, T: Ord> {
+    /// The nodes whose value and right subtree are still to come, the next
+    /// on top.
+    stack: Vec<&'a Node<T>>,
+}
+
+impl<'a, T: Ord> Iter<'a, T> {
+    fn push_left(&mut self, mut subtree: &'a Subtree<T>) {
+        while let Some(n) = subtree.0.as_deref() {
+            self.stack.push(n);
+            subtree = &n.left;
+        }
+    }
+}
+
+impl<'a, T: Ord> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let n = self.stack.pop()?;
+        self.push_left(&n.right);
+        Some(&n.value)
+    }
+}
+
+/// Borrowing pre-order iterator over a [`BinaryTree`].
+pub struct PreOrder<'a, T: Ord> {
+    /// The roots of the subtrees still to visit, the next on top.
+    stack: Vec<&'a Node<T>>,
+}
+
+impl<'a, T: Ord> Iterator for PreOrder<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let n = self.stack.pop()?;
+        self.stack.extend(n.right.0.as_deref());
+        self.stack.extend(n.left.0.as_deref());
+        Some(&n.value)
+    }
+}
+
+/// Borrowing post-order iterator over a [`BinaryTree`].
+pub struct PostOrder<'a, T: Ord> {
+    /// The nodes still to visit, the next on top, each with whether its
+    /// subtrees have been visited already.
+    stack: Vec<(&'a Node<T>, bool)>,
+}
+
+impl<'a, T: Ord> Iterator for PostOrder<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            let (n, visited) = self.stack.pop()?;
+            if visited {
+                return Some(&n.value);
+            }
+            self.stack.push((n, true));
+            self.stack
+                .extend(n.right.0.as_deref().map(|right| (right, false)));
+            self.stack
+                .extend(n.left.0.as_deref().map(|left| (left, false)));
+        }
+    }
+}
+
+/// Owning in-order iterator over a [`BinaryTree`].
+pub struct IntoIter<T: Ord> {
+    /// The nodes whose value and right subtree are still to come, the next
+    /// on top, with their left subtrees taken out already.
+    stack: Vec<Box<Node<T>>>,
+}
+
+impl<T: Ord> IntoIter<T> {
+    fn push_left(&mut self, mut subtree: Subtree<T>) {
+        while let Some(mut n) = subtree.0 {
+            subtree = std::mem::replace(&mut n.left, Subtree::new());
+            self.stack.push(n);
+        }
+    }
+}
+
+impl<T: Ord> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let n = self.stack.pop()?;
+        let Node { value, right, .. } = *n;
+        self.push_left(right);
+        Some(value)
+    }
+}
+
+impl<T: Ord> IntoIterator for BinaryTree<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        let mut iter = IntoIter { stack: Vec::new() };
+        iter.push_left(self.root);
+        iter
+    }
+}
+
+impl<'a, T: Ord> IntoIterator for &'a BinaryTree<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
 }
 
 #[cfg(test)]
@@ -189,6 +395,74 @@ mod tests {
         check_has(&tree, &[true, false, false, true, true]);
     }
 
+    #[test]
+    fn remove() {
+        let mut tree = BinaryTree::new();
+        for i in [5, 2, 8, 1, 4, 7, 9, 3, 6] {
+            tree.insert(i);
+        }
+        assert!(!tree.remove(&10));
+        assert!(tree.remove(&1)); // a leaf
+        assert!(tree.remove(&4)); // one child
+        assert!(tree.remove(&5)); // two children, the root
+        assert!(!tree.remove(&5));
+        assert_eq!(tree.len(), 6);
+        assert!(!tree.has(&5) && tree.has(&6));
+        assert_eq!(tree.iter().copied().collect::<Vec<_>>(), [2, 3, 6, 7, 8, 9]);
+        for i in [2, 3, 6, 7, 8, 9] {
+            assert!(tree.remove(&i));
+        }
+        assert!(tree.is_empty());
+
+        // The successor of 50 is 60, below 70 rather than its right child.
+        let mut tree = BinaryTree::new();
+        for i in [50, 30, 70, 60, 65, 80] {
+            tree.insert(i);
+        }
+        assert!(tree.remove(&50));
+        assert_eq!(
+            tree.iter().copied().collect::<Vec<_>>(),
+            [30, 60, 65, 70, 80]
+        );
+        assert_eq!(tree.len(), 5);
+    }
+
+    #[test]
+    fn min_max() {
+        let mut tree = BinaryTree::new();
+        assert_eq!((tree.min(), tree.max()), (None, None));
+        for i in [4, 7, 1, 9, 3] {
+            tree.insert(i);
+        }
+        assert_eq!((tree.min(), tree.max()), (Some(&1), Some(&9)));
+        tree.remove(&9);
+        assert_eq!(tree.max(), Some(&7));
+    }
+
+    #[test]
+    fn iterators() {
+        let mut tree = BinaryTree::new();
+        for i in [4, 2, 6, 1, 3, 5, 7] {
+            tree.insert(i);
+        }
+        let values = |iter: &mut dyn Iterator<Item = &i32>| iter.copied().collect::<Vec<_>>();
+        assert_eq!(values(&mut tree.iter()), [1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(values(&mut tree.pre_order()), [4, 2, 1, 3, 6, 5, 7]);
+        assert_eq!(values(&mut tree.post_order()), [1, 3, 2, 5, 7, 6, 4]);
+        assert_eq!((&tree).into_iter().count(), 7);
+        assert_eq!(BinaryTree::<i32>::new().pre_order().next(), None);
+
+        let words: BinaryTree<String> = {
+            let mut tree = BinaryTree::new();
+            for word in ["pear", "apple", "quince", "fig"] {
+                tree.insert(String::from(word));
+            }
+            tree
+        };
+        let owned: Vec<String> = words.into_iter().collect();
+        assert_eq!(owned, ["apple", "fig", "pear", "quince"]);
+    }
+
     #[test]
     fn unbalanced() {
         let mut tree = BinaryTree::new();